@@ -2,8 +2,8 @@ use blue_engine::{
     primitive_shapes::{cube, square, triangle, uv_sphere},
     uniform_type::Matrix,
     utils::{default_resources::DEFAULT_MATRIX_4, flycamera::FlyCamera, loader::load_gltf},
-    Engine, LightManager, ObjectSettings, PolygonMode, PowerPreference, RotateAxis, ShaderSettings,
-    TextureData, Vertex, WindowDescriptor,
+    Engine, LightManager, ObjectSettings, PolygonMode, PowerPreference, RenderGraph,
+    RenderGraphPass, RotateAxis, ShaderSettings, TextureData, Vertex, WindowDescriptor,
 };
 
 fn main() {
@@ -14,13 +14,14 @@ fn main() {
         decorations: true,
         resizable: true,
         power_preference: PowerPreference::HighPerformance,
+        sample_count: 1,
     })
     .expect("win");
 
     // ===============================
 
     //let triangle_id = triangle(Some("Triangleee"), &mut engine, camera).unwrap();
-    let window_size = engine.window.inner_size();
+    let window_size = engine.window.as_ref().unwrap().inner_size();
 
     uv_sphere("cube", &mut engine, (18, 36, 1f32)).unwrap();
     engine.objects.get_mut("cube").unwrap().scale(0.6, 0.6, 0.6);
@@ -62,7 +63,7 @@ fn main() {
     engine.objects[sphere_4].position(-2f32, -1f32, 0f32);
     engine.objects[sphere_4].set_color(1.0f32, 0.5f32, 0.31f32, 1f32); */
 
-    //let window_size = engine.window.inner_size();
+    //let window_size = engine.window.as_ref().unwrap().inner_size();
     /*let change_texture = engine
     .renderer
     .build_and_append_texture(
@@ -97,22 +98,33 @@ fn main() {
     let mut lm = LightManager::new();
     lm.set_object_as_light("cube");
 
-    engine.renderer.custom_render_pass = Some(Box::new(|encoder, view| {
-        let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: true,
-                },
-            })],
-            depth_stencil_attachment: None,
-        });
-
-        drop(render_pass);
-    }));
+    engine
+        .renderer
+        .render_graph
+        .add_pass(RenderGraphPass {
+            color_outputs: vec![RenderGraph::FRAME.into()],
+            ..RenderGraphPass::new("overlay", |context| {
+                let (view, resolve_target) = context.color_attachment(RenderGraph::FRAME)?;
+                let render_pass = context
+                    .encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Overlay pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view,
+                            resolve_target,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+
+                drop(render_pass);
+                Ok(())
+            })
+        })
+        .unwrap();
 
     engine
         .update_loop(move |renderer, window, objects, input, camera| {
@@ -33,7 +33,7 @@ pub fn square(name: &'static str, engine: &mut Engine) -> anyhow::Result<()> {
                 normal: [0f32, 0f32, 0f32],
             },
         ],
-        vec![2, 1, 0, 2, 0, 3],
+        vec![2u16, 1, 0, 2, 0, 3],
         ObjectSettings {
             name: name,
             camera_effect: false,
@@ -0,0 +1,215 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::header::{AtlasRegion, Renderer, TextureAtlas, TextureData, TextureSettings};
+use std::collections::HashMap;
+
+/// The pixels around each packed image, filled with its edges so filtering doesn't
+/// blend neighbouring images together
+const PADDING: u32 = 1;
+
+impl AtlasRegion {
+    pub(crate) fn from_pixels(x: u32, y: u32, width: u32, height: u32, size: (u32, u32)) -> Self {
+        Self {
+            x: x as f32 / size.0 as f32,
+            y: y as f32 / size.1 as f32,
+            width: width as f32 / size.0 as f32,
+            height: height as f32 / size.1 as f32,
+        }
+    }
+
+    /// Maps texture coordinates of a whole texture to the same place within the region
+    pub fn uv(&self, uv: [f32; 2]) -> [f32; 2] {
+        [self.x + uv[0] * self.width, self.y + uv[1] * self.height]
+    }
+}
+
+impl TextureAtlas {
+    /// Returns the region of the image with the name
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    /// The names of all the images in the atlas
+    pub fn region_names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(|name| name.as_str())
+    }
+}
+
+impl Renderer {
+    /// Packs the images into a single texture, with each image addressed by its name
+    pub fn build_texture_atlas(
+        &mut self,
+        name: &'static str,
+        images: Vec<(&str, TextureData)>,
+        settings: TextureSettings,
+    ) -> anyhow::Result<TextureAtlas> {
+        let mut loaded = Vec::with_capacity(images.len());
+        for (image_name, texture_data) in images {
+            if loaded
+                .iter()
+                .any(|(loaded_name, _)| *loaded_name == image_name)
+            {
+                anyhow::bail!(
+                    "The atlas {} has more than one image named {}",
+                    name,
+                    image_name
+                );
+            }
+            let img = self
+                .load_image_or_default(image_name, texture_data, settings.fallback)?
+                .to_rgba8();
+            loaded.push((image_name, img));
+        }
+
+        // the images are placed on shelves, from the tallest to the shortest
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        let padded =
+            |img: &image::RgbaImage| (img.width() + PADDING * 2, img.height() + PADDING * 2);
+        let area: u32 = loaded
+            .iter()
+            .map(|(_, img)| padded(img).0 * padded(img).1)
+            .sum();
+        let widest = loaded
+            .iter()
+            .map(|(_, img)| padded(img).0)
+            .max()
+            .unwrap_or(1);
+        let width = ((area as f32).sqrt().ceil() as u32)
+            .next_power_of_two()
+            .max(widest)
+            .min(max_dimension);
+
+        let mut order: Vec<usize> = (0..loaded.len()).collect();
+        order.sort_by_key(|i| std::cmp::Reverse(loaded[*i].1.height()));
+        let mut placements = vec![(0, 0); loaded.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for i in order {
+            let (image_width, image_height) = padded(&loaded[i].1);
+            if x + image_width > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            placements[i] = (x, y);
+            x += image_width;
+            shelf_height = shelf_height.max(image_height);
+        }
+        let height = (y + shelf_height).max(1);
+        if widest > max_dimension || height > max_dimension {
+            anyhow::bail!(
+                "The images of the atlas {} don't fit in a texture of at most {}x{}",
+                name,
+                max_dimension,
+                max_dimension
+            );
+        }
+
+        let mut atlas = image::RgbaImage::new(width, height);
+        let mut regions = HashMap::with_capacity(loaded.len());
+        for ((image_name, img), (x, y)) in loaded.into_iter().zip(placements) {
+            let (image_width, image_height) = padded(&img);
+            for py in 0..image_height {
+                for px in 0..image_width {
+                    let source_x = px.saturating_sub(PADDING).min(img.width() - 1);
+                    let source_y = py.saturating_sub(PADDING).min(img.height() - 1);
+                    atlas.put_pixel(x + px, y + py, *img.get_pixel(source_x, source_y));
+                }
+            }
+            regions.insert(
+                image_name.to_string(),
+                AtlasRegion::from_pixels(
+                    x + PADDING,
+                    y + PADDING,
+                    img.width(),
+                    img.height(),
+                    (width, height),
+                ),
+            );
+        }
+
+        let texture = self.build_texture_with_settings(
+            name,
+            TextureData::Image(image::DynamicImage::ImageRgba8(atlas)),
+            settings,
+        )?;
+
+        Ok(TextureAtlas {
+            texture,
+            size: (width, height),
+            regions,
+        })
+    }
+
+    /// Loads a sprite sheet with its regions from a JSON description, in the format
+    /// exported by TexturePacker, with the frames either as a hash or an array
+    #[cfg(feature = "sprite_sheet_loader")]
+    pub fn load_sprite_sheet(
+        &mut self,
+        name: &'static str,
+        texture_data: TextureData,
+        description: &str,
+        settings: TextureSettings,
+    ) -> anyhow::Result<TextureAtlas> {
+        let description: serde_json::Value = serde_json::from_str(description)?;
+        let img = self.load_image_or_default(name, texture_data, settings.fallback)?;
+        let size = (img.width(), img.height());
+
+        let mut regions = HashMap::new();
+        match &description["frames"] {
+            serde_json::Value::Object(frames) => {
+                for (frame_name, frame) in frames {
+                    regions.insert(frame_name.clone(), frame_region(frame_name, frame, size)?);
+                }
+            }
+            serde_json::Value::Array(frames) => {
+                for frame in frames {
+                    let frame_name = frame["filename"]
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("A frame of {} has no filename", name))?;
+                    regions.insert(
+                        frame_name.to_string(),
+                        frame_region(frame_name, frame, size)?,
+                    );
+                }
+            }
+            _ => anyhow::bail!("The sprite sheet {} has no frames", name),
+        }
+
+        let texture = self.build_texture_with_settings(name, TextureData::Image(img), settings)?;
+
+        Ok(TextureAtlas {
+            texture,
+            size,
+            regions,
+        })
+    }
+}
+
+#[cfg(feature = "sprite_sheet_loader")]
+fn frame_region(
+    name: &str,
+    frame: &serde_json::Value,
+    size: (u32, u32),
+) -> anyhow::Result<AtlasRegion> {
+    if frame["rotated"].as_bool() == Some(true) {
+        anyhow::bail!("The frame {} is rotated, which isn't supported", name);
+    }
+
+    let rect = &frame["frame"];
+    let field = |key: &str| {
+        rect[key]
+            .as_u64()
+            .map(|value| value as u32)
+            .ok_or_else(|| anyhow::anyhow!("The frame {} has no {}", name, key))
+    };
+    let (x, y, width, height) = (field("x")?, field("y")?, field("w")?, field("h")?);
+    if x + width > size.0 || y + height > size.1 {
+        anyhow::bail!("The frame {} is outside of the sprite sheet", name);
+    }
+
+    Ok(AtlasRegion::from_pixels(x, y, width, height, size))
+}
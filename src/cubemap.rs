@@ -0,0 +1,307 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::{
+    header::{
+        uniform_type, Camera, Cubemap, Renderer, Shaders, TextureData, TextureSettings,
+        UniformBuffers,
+    },
+    utils::default_resources::{DEFAULT_MATRIX_4, SKYBOX_SHADER},
+};
+use wgpu::util::DeviceExt;
+
+impl Renderer {
+    pub(crate) fn build_cubemap_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("cubemap_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Creates a cubemap from six square images of the same size, in the order
+    /// +X, -X, +Y, -Y, +Z, -Z
+    pub fn build_cubemap(
+        &mut self,
+        name: &'static str,
+        faces: [TextureData; 6],
+        settings: TextureSettings,
+    ) -> anyhow::Result<Cubemap> {
+        let mut images = Vec::with_capacity(6);
+        for face in faces {
+            images.push(
+                self.load_image_or_default(name, face, settings.fallback)?
+                    .to_rgba8(),
+            );
+        }
+
+        let size = images[0].width();
+        if images
+            .iter()
+            .any(|img| img.width() != size || img.height() != size)
+        {
+            anyhow::bail!(
+                "The faces of the cubemap {} have to be square and of the same size",
+                name
+            );
+        }
+
+        self.build_cubemap_from_faces(name, images, settings)
+    }
+
+    /// Creates a cubemap from an equirectangular panorama, with faces of the given size.
+    /// The center of the panorama is in the -Z direction
+    pub fn build_cubemap_from_equirectangular(
+        &mut self,
+        name: &'static str,
+        panorama: TextureData,
+        face_size: u32,
+        settings: TextureSettings,
+    ) -> anyhow::Result<Cubemap> {
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        if face_size == 0 || face_size > max_dimension {
+            anyhow::bail!(
+                "The faces of the cubemap {} have to be between 1 and {} pixels",
+                name,
+                max_dimension
+            );
+        }
+
+        let panorama = self
+            .load_image_or_default(name, panorama, settings.fallback)?
+            .to_rgba8();
+        let faces = (0..6)
+            .map(|face| {
+                image::RgbaImage::from_fn(face_size, face_size, |x, y| {
+                    let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+                    let direction = face_direction(face, u, v).normalize();
+
+                    let longitude = direction.x.atan2(-direction.z);
+                    let latitude = direction.y.asin();
+                    sample_bilinear(
+                        &panorama,
+                        0.5 + longitude / std::f32::consts::TAU,
+                        0.5 - latitude / std::f32::consts::PI,
+                    )
+                })
+            })
+            .collect();
+
+        self.build_cubemap_from_faces(name, faces, settings)
+    }
+
+    fn build_cubemap_from_faces(
+        &self,
+        name: &'static str,
+        faces: Vec<image::RgbaImage>,
+        settings: TextureSettings,
+    ) -> anyhow::Result<Cubemap> {
+        let sampler = self.build_sampler(name, &settings.sampler)?;
+        let size = faces[0].width();
+        let mip_level_count = settings.mipmaps.level_count(size, size);
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(name),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (layer, mut rgba) in faces.into_iter().enumerate() {
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    let level_size = (rgba.width() / 2).max(1);
+                    rgba = image::imageops::resize(
+                        &rgba,
+                        level_size,
+                        level_size,
+                        image::imageops::FilterType::Triangle,
+                    );
+                }
+                self.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &rgba,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(4 * rgba.width()),
+                        rows_per_image: std::num::NonZeroU32::new(rgba.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: rgba.width(),
+                        height: rgba.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(name),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(name),
+            layout: &self.cubemap_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Ok(Cubemap {
+            bind_group: std::sync::Arc::new(bind_group),
+            size,
+        })
+    }
+
+    /// Creates the pipeline and uniform buffer used to draw skybox backgrounds
+    pub(crate) fn build_skybox(&self) -> anyhow::Result<(Shaders, wgpu::Buffer, UniformBuffers)> {
+        let pipeline = self.build_fullscreen_pipeline(
+            "Skybox",
+            SKYBOX_SHADER,
+            &[
+                &self.cubemap_bind_group_layout,
+                &self.default_uniform_bind_group_layout,
+            ],
+            self.render_format(),
+            Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            self.sample_count,
+        );
+
+        let buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Skybox Uniform"),
+                contents: bytemuck::cast_slice(&[DEFAULT_MATRIX_4]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox Bind Group"),
+            layout: &self.default_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Ok((pipeline, buffer, bind_group))
+    }
+
+    /// Turns the skybox with the camera, leaving out its position
+    pub(crate) fn update_skybox(&self, camera: &Camera) {
+        let direction = if camera.add_position_and_target {
+            camera.target
+        } else {
+            camera.target - camera.position
+        };
+        let view = nalgebra_glm::look_at_rh(&nalgebra_glm::Vec3::zeros(), &direction, &camera.up);
+        // the same projection as `Camera::build_view_projection_matrix`
+        let projection = nalgebra_glm::perspective(
+            camera.fov,
+            camera.resolution.0 / camera.resolution.1,
+            camera.near,
+            camera.far,
+        );
+
+        self.queue.write_buffer(
+            &self.skybox_data.as_ref().unwrap().1,
+            0,
+            bytemuck::cast_slice(&[uniform_type::Matrix::from_im(nalgebra_glm::inverse(
+                &(projection * view),
+            ))]),
+        );
+    }
+}
+
+/// The direction through a point of a face, with the coordinates on the face going from -1 to 1
+fn face_direction(face: usize, u: f32, v: f32) -> nalgebra_glm::Vec3 {
+    match face {
+        0 => nalgebra_glm::vec3(1.0, -v, -u),
+        1 => nalgebra_glm::vec3(-1.0, -v, u),
+        2 => nalgebra_glm::vec3(u, 1.0, v),
+        3 => nalgebra_glm::vec3(u, -1.0, -v),
+        4 => nalgebra_glm::vec3(u, -v, 1.0),
+        _ => nalgebra_glm::vec3(-u, -v, -1.0),
+    }
+}
+
+/// Samples the image between its pixels, wrapping around horizontally
+fn sample_bilinear(img: &image::RgbaImage, u: f32, v: f32) -> image::Rgba<u8> {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |x: i64, y: i64| {
+        img.get_pixel(x.rem_euclid(width) as u32, y.clamp(0, height - 1) as u32)
+            .0
+    };
+
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let corners = [
+        (pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (pixel(x0 + 1, y0), fx * (1.0 - fy)),
+        (pixel(x0, y0 + 1), (1.0 - fx) * fy),
+        (pixel(x0 + 1, y0 + 1), fx * fy),
+    ];
+    let mut result = [0u8; 4];
+    for (channel, value) in result.iter_mut().enumerate() {
+        let sum: f32 = corners
+            .iter()
+            .map(|(color, weight)| color[channel] as f32 * weight)
+            .sum();
+        *value = sum.round().clamp(0.0, 255.0) as u8;
+    }
+
+    image::Rgba(result)
+}
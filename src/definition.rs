@@ -8,9 +8,12 @@ use image::GenericImageView;
 use wgpu::{util::DeviceExt, BindGroupLayout, Sampler, Texture, TextureView};
 
 use crate::header::{
-    Pipeline, ShaderSettings, Shaders, TextureData, TextureMode, Textures, UniformBuffers, Vertex,
-    VertexBuffers,
+    Indices, Instance, InstanceBuffers, InstanceRaw, Pipeline, PipelineKey, RenderTarget,
+    SamplerSettings, ShaderSettings, Shaders, TextureData, TextureError, TextureMode,
+    TextureSettings, Textures, UniformBuffers, Vertex, VertexBuffers, VertexLayout,
+    VertexLayoutDescriptor,
 };
+use crate::utils::default_resources::{BACKGROUND_SHADER, DEFAULT_COLOR, DEFAULT_TEXTURE};
 
 impl crate::header::Renderer {
     /// Creates a new render pipeline. Could be thought of as like materials in game engines.
@@ -26,6 +29,7 @@ impl crate::header::Renderer {
             vertex_buffer,
             texture,
             uniform,
+            instance_buffer: self.build_instance_buffer(&[Instance::default()])?,
         })
     }
 
@@ -37,6 +41,32 @@ impl crate::header::Renderer {
         uniform_layout: Option<&BindGroupLayout>,
         settings: ShaderSettings,
     ) -> Result<Shaders, anyhow::Error> {
+        self.build_shader_with_layout(
+            name,
+            shader_source,
+            uniform_layout,
+            &Vertex::layout(),
+            settings,
+        )
+    }
+
+    /// Creates a shader group for vertices of the given layout, e.g. `MyVertex::layout()`.
+    /// The instance attributes are placed at the shader locations after the vertex attributes
+    pub fn build_shader_with_layout(
+        &mut self,
+        name: &str,
+        shader_source: String,
+        uniform_layout: Option<&BindGroupLayout>,
+        vertex_layout: &VertexLayoutDescriptor,
+        settings: ShaderSettings,
+    ) -> Result<Shaders, anyhow::Error> {
+        let instance_attributes = InstanceRaw::attributes(vertex_layout.next_location());
+        let instance_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &instance_attributes,
+        };
+
         let shader = self
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -45,12 +75,24 @@ impl crate::header::Renderer {
             });
 
         let mut bind_group_layouts = vec![
-            &self.texture_bind_group_layout,
+            if settings.material {
+                &self.material_bind_group_layout
+            } else {
+                &self.texture_bind_group_layout
+            },
             &self.default_uniform_bind_group_layout,
         ];
         if uniform_layout.is_some() {
             bind_group_layouts.push(uniform_layout.unwrap())
         }
+        if settings.lighting {
+            if uniform_layout.is_none() {
+                anyhow::bail!(
+                    "Shaders with shadow maps need a uniform layout, as they are bound after it"
+                );
+            }
+            bind_group_layouts.push(&self.lighting_bind_group_layout);
+        }
 
         let render_pipeline_layout =
             self.device
@@ -68,15 +110,15 @@ impl crate::header::Renderer {
                 vertex: wgpu::VertexState {
                     module: &shader,
                     entry_point: "vs_main",
-                    buffers: &[Vertex::desc()],
+                    buffers: &[vertex_layout.desc(), instance_layout],
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &shader,
                     entry_point: "fs_main",
                     targets: &[Some(wgpu::ColorTargetState {
-                        format: self.config.format,
+                        format: self.render_format(),
                         write_mask: wgpu::ColorWrites::ALL,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        blend: Some(settings.blend_mode.blend_state()),
                     })],
                 }),
                 primitive: wgpu::PrimitiveState {
@@ -91,25 +133,174 @@ impl crate::header::Renderer {
                 },
                 depth_stencil: Some(wgpu::DepthStencilState {
                     format: Self::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
+                    depth_write_enabled: !settings.blend_mode.is_transparent(),
+                    depth_compare: settings
+                        .depth_compare
+                        .unwrap_or(wgpu::CompareFunction::Less),
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
                 multisample: wgpu::MultisampleState {
-                    count: settings.count,
+                    count: self.sample_count,
                     mask: settings.mask,
                     alpha_to_coverage_enabled: settings.alpha_to_coverage_enabled,
                 },
                 multiview: None,
             });
 
-        Ok(render_pipeline)
+        Ok(std::sync::Arc::new(render_pipeline))
+    }
+
+    /// Returns the pipeline for the shader and settings from the cache, building it if it
+    /// doesn't exist yet. The `uniform_count` is the amount of uniform buffers in the layout.
+    ///
+    /// The cache tells uniform layouts apart only by that count, so the layout has to come
+    /// from `build_uniform_buffer`, where the count decides the whole layout. Pipelines with
+    /// a layout made any other way should be built with `build_shader_with_layout` instead
+    pub fn build_cached_shader(
+        &mut self,
+        name: &str,
+        shader_source: String,
+        uniform_layout: Option<&BindGroupLayout>,
+        uniform_count: usize,
+        vertex_layout: &VertexLayoutDescriptor,
+        settings: ShaderSettings,
+    ) -> Result<Shaders, anyhow::Error> {
+        let key = PipelineKey {
+            shader_source,
+            settings: ShaderSettings {
+                count: self.sample_count,
+                ..settings
+            },
+            uniform_count,
+            vertex_layout: vertex_layout.clone(),
+            format: self.render_format(),
+            sample_count: self.sample_count,
+        };
+
+        if let Some(shader) = self.pipeline_cache.pipelines.get(&key) {
+            self.pipeline_cache.hits += 1;
+            return Ok(shader.clone());
+        }
+
+        let shader = self.build_shader_with_layout(
+            name,
+            key.shader_source.clone(),
+            uniform_layout,
+            vertex_layout,
+            settings,
+        )?;
+        self.pipeline_cache.misses += 1;
+        self.pipeline_cache.pipelines.insert(key, shader.clone());
+
+        Ok(shader)
     }
 
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    /// Creates a new texture data
+    /// Creates a pipeline that draws a single triangle covering the whole target, without vertex buffers.
+    /// Used for backgrounds and screen effects.
+    pub fn build_fullscreen_pipeline(
+        &self,
+        name: &str,
+        shader_source: &str,
+        bind_group_layouts: &[&BindGroupLayout],
+        format: wgpu::TextureFormat,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        sample_count: u32,
+    ) -> Shaders {
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(format!("{} Shader", name).as_str()),
+                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+            });
+
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(format!("{} Pipeline Layout", name).as_str()),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(name),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        write_mask: wgpu::ColorWrites::ALL,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        std::sync::Arc::new(pipeline)
+    }
+
+    /// Creates the pipeline and uniform buffer used to draw gradient and texture backgrounds
+    pub(crate) fn build_background(
+        &self,
+    ) -> anyhow::Result<(Shaders, wgpu::Buffer, UniformBuffers)> {
+        let pipeline = self.build_fullscreen_pipeline(
+            "Background",
+            BACKGROUND_SHADER,
+            &[
+                &self.texture_bind_group_layout,
+                &self.default_uniform_bind_group_layout,
+            ],
+            self.render_format(),
+            Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            self.sample_count,
+        );
+
+        let buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Background Uniform"),
+                contents: bytemuck::cast_slice(&[DEFAULT_COLOR, DEFAULT_COLOR]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Background Bind Group"),
+            layout: &self.default_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Ok((pipeline, buffer, bind_group))
+    }
+
+    /// Creates a new texture data, with a full mip chain
     pub fn build_texture(
         &mut self,
         name: &'static str,
@@ -117,12 +308,24 @@ impl crate::header::Renderer {
         texture_mode: TextureMode,
         //texture_format: TextureFormat,
     ) -> anyhow::Result<Textures> {
-        let mode: wgpu::AddressMode;
-        match texture_mode {
-            TextureMode::Clamp => mode = wgpu::AddressMode::Repeat,
-            TextureMode::Repeat => mode = wgpu::AddressMode::MirrorRepeat,
-            TextureMode::MirrorRepeat => mode = wgpu::AddressMode::ClampToEdge,
-        }
+        self.build_texture_with_settings(
+            name,
+            texture_data,
+            TextureSettings {
+                sampler: SamplerSettings::with_mode(texture_mode),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Creates a new texture data, with the sampler and mip chain of the settings
+    pub fn build_texture_with_settings(
+        &mut self,
+        name: &'static str,
+        texture_data: TextureData,
+        settings: TextureSettings,
+    ) -> anyhow::Result<Textures> {
+        let sampler = self.build_sampler(name, &settings.sampler)?;
 
         /*let img_format = match texture_format {
             TextureFormat::PNG => image::ImageFormat::Png,
@@ -131,15 +334,148 @@ impl crate::header::Renderer {
             TextureFormat::PNM => image::ImageFormat::Pnm,
         };*/
 
+        let img = self.load_image_or_default(name, texture_data, settings.fallback)?;
+        let mip_level_count = settings.mipmaps.level_count(img.width(), img.height());
+        let view = self.build_texture_view(
+            name,
+            &img,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            mip_level_count,
+        );
+
+        let diffuse_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            label: Some("Diffuse Bind Group"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Ok(std::sync::Arc::new(diffuse_bind_group))
+    }
+
+    /// Decodes the image of a texture, or the default texture with a warning when it
+    /// can't be loaded and `fallback` is set
+    pub(crate) fn load_image_or_default(
+        &self,
+        name: &str,
+        texture_data: TextureData,
+        fallback: bool,
+    ) -> anyhow::Result<image::DynamicImage> {
+        match self.load_image(texture_data) {
+            Ok(img) => Ok(img),
+            Err(error) if fallback => {
+                log::warn!("Using the default texture for {}: {}", name, error);
+                Ok(image::load_from_memory(DEFAULT_TEXTURE)?)
+            }
+            Err(error) => {
+                Err(anyhow::Error::new(error)
+                    .context(format!("Couldn't load the texture {}", name)))
+            }
+        }
+    }
+
+    /// Decodes the image of a texture, and checks that it fits in a texture of the device
+    pub(crate) fn load_image(
+        &self,
+        texture_data: TextureData,
+    ) -> Result<image::DynamicImage, TextureError> {
+        let decode = |data: &[u8]| {
+            image::load_from_memory(data).map_err(|error| match error {
+                image::ImageError::Unsupported(error) => {
+                    TextureError::UnsupportedFormat(error.to_string())
+                }
+                error => TextureError::Decode(error.to_string()),
+            })
+        };
         let img = match texture_data {
-            TextureData::Bytes(data) => image::load_from_memory(data.as_slice())
-                .expect(format!("Couldn't Load Image For Texture Of {}", name).as_str()),
+            TextureData::Bytes(data) => decode(data.as_slice())?,
             TextureData::Image(data) => data,
-            TextureData::Path(path) => image::open(path)
-                .expect(format!("Couldn't Load Image For Texture Of {}", name).as_str()),
+            TextureData::Path(path) => match std::fs::read(path) {
+                Ok(data) => decode(data.as_slice())?,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(TextureError::NotFound(path.into()))
+                }
+                Err(error) => return Err(TextureError::Io(error)),
+            },
         };
 
-        let rgba = img.to_rgba8();
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        if img.width() > max_dimension || img.height() > max_dimension {
+            return Err(TextureError::TooLarge {
+                width: img.width(),
+                height: img.height(),
+                max_dimension,
+            });
+        }
+
+        Ok(img)
+    }
+
+    /// Creates a sampler, after checking the settings are supported by the device
+    pub fn build_sampler(
+        &self,
+        name: &str,
+        settings: &SamplerSettings,
+    ) -> anyhow::Result<wgpu::Sampler> {
+        let modes = [settings.mode_u, settings.mode_v, settings.mode_w];
+        if modes.contains(&TextureMode::Border)
+            && !self
+                .device
+                .features()
+                .contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+        {
+            anyhow::bail!(
+                "The border texture mode of {} isn't supported by the device",
+                name
+            );
+        }
+        if settings.anisotropy == 0
+            || settings.anisotropy > 16
+            || !settings.anisotropy.is_power_of_two()
+        {
+            anyhow::bail!(
+                "The anisotropy of {} must be 1, 2, 4, 8 or 16, got {}",
+                name,
+                settings.anisotropy
+            );
+        }
+
+        Ok(self.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(name),
+            address_mode_u: settings.mode_u.address_mode(),
+            address_mode_v: settings.mode_v.address_mode(),
+            address_mode_w: settings.mode_w.address_mode(),
+            mag_filter: settings.mag_filter,
+            min_filter: settings.min_filter,
+            mipmap_filter: settings.mipmap_filter,
+            anisotropy_clamp: std::num::NonZeroU8::new(settings.anisotropy)
+                .filter(|anisotropy| anisotropy.get() > 1),
+            border_color: modes
+                .contains(&TextureMode::Border)
+                .then_some(settings.border_color),
+            ..Default::default()
+        }))
+    }
+
+    /// Uploads the image into a new texture of the given format, `Rgba8UnormSrgb` for colors
+    /// or `Rgba8Unorm` for data such as normals. The levels after the first are downsampled
+    /// from the level before them
+    pub(crate) fn build_texture_view(
+        &self,
+        name: &str,
+        img: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+        mip_level_count: u32,
+    ) -> wgpu::TextureView {
+        let mut rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -150,62 +486,51 @@ impl crate::header::Renderer {
         let texture = self.device.create_texture(&wgpu::TextureDescriptor {
             label: Some(name),
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
-            },
-            size,
-        );
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: mode,
-            address_mode_v: mode,
-            address_mode_w: mode,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let diffuse_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.texture_bind_group_layout,
-            label: Some("Diffuse Bind Group"),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&view),
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                rgba = image::imageops::resize(
+                    &rgba,
+                    (rgba.width() / 2).max(1),
+                    (rgba.height() / 2).max(1),
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+            self.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * rgba.width()),
+                    rows_per_image: std::num::NonZeroU32::new(rgba.height()),
                 },
-            ],
-        });
+                wgpu::Extent3d {
+                    width: rgba.width(),
+                    height: rgba.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
-        Ok(diffuse_bind_group)
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 
     pub(crate) fn build_depth_buffer(
         label: &str,
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
     ) -> (Texture, TextureView, Sampler) {
         let size = wgpu::Extent3d {
             width: config.width,
@@ -216,7 +541,7 @@ impl crate::header::Renderer {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
@@ -240,6 +565,142 @@ impl crate::header::Renderer {
         return (texture, view, sampler);
     }
 
+    /// Creates a new render target of the given size, which objects can be rendered into
+    pub fn build_render_target(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<RenderTarget> {
+        let config = wgpu::SurfaceConfiguration {
+            width,
+            height,
+            format: self.render_format(),
+            ..self.config.clone()
+        };
+
+        Ok(RenderTarget {
+            color: Self::build_color_target(name, &self.device, &config),
+            depth: Self::build_depth_buffer(
+                format!("{} Depth Buffer", name).as_str(),
+                &self.device,
+                &config,
+                self.sample_count,
+            ),
+            msaa: Self::build_msaa_buffer(
+                format!("{} MSAA Buffer", name).as_str(),
+                &self.device,
+                &config,
+                self.sample_count,
+            ),
+            size: (width, height),
+            format: config.format,
+        })
+    }
+
+    /// Creates a texture from the color of a render target, that can be set on objects.
+    /// The texture keeps showing whatever is rendered to the target afterwards.
+    pub fn build_render_target_texture(
+        &mut self,
+        target: &RenderTarget,
+    ) -> anyhow::Result<Textures> {
+        let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(std::sync::Arc::new(self.device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &self.texture_bind_group_layout,
+                label: Some("Render Target Bind Group"),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&target.color.1),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            },
+        )))
+    }
+
+    /// The color format used when there is no surface to get a format from
+    pub const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// The color format the scene is rendered in when HDR is enabled
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    /// The color format objects are rendered in, which is the format of the frame unless HDR is enabled
+    pub fn render_format(&self) -> wgpu::TextureFormat {
+        match self.hdr {
+            Some(_) => Self::HDR_FORMAT,
+            None => self.config.format,
+        }
+    }
+
+    /// Creates the multisampled color target for MSAA, or nothing if multisampling is off
+    pub(crate) fn build_msaa_buffer(
+        label: &str,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Option<(Texture, TextureView)> {
+        if sample_count <= 1 {
+            return None;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Some((texture, view))
+    }
+
+    pub(crate) fn build_color_target(
+        label: &str,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> (Texture, TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        (texture, view)
+    }
+
     pub fn build_uniform_buffer_part<T: bytemuck::Zeroable + bytemuck::Pod>(
         &self,
         name: &str,
@@ -249,14 +710,14 @@ impl crate::header::Renderer {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(name),
                 contents: bytemuck::cast_slice(&[value]),
-                usage: wgpu::BufferUsages::UNIFORM,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             })
     }
 
     /// Creates a new uniform buffer group, according to a list of types
     pub fn build_uniform_buffer(
         &mut self,
-        uniforms: &Vec<wgpu::Buffer>,
+        uniforms: &[wgpu::Buffer],
     ) -> Result<(UniformBuffers, BindGroupLayout), anyhow::Error> {
         let mut buffer_entry = Vec::<wgpu::BindGroupEntry>::new();
         let mut buffer_layout = Vec::<wgpu::BindGroupLayoutEntry>::new();
@@ -334,32 +795,192 @@ impl crate::header::Renderer {
         Ok((uniform_bind_group, uniform_bind_group_layout))
     }
 
-    /// Creates a new vertex buffer and indecies
-    pub fn build_vertex_buffer(
+    /// Creates a new vertex buffer and indecies. The vertices can be of any type implementing `VertexLayout`
+    pub fn build_vertex_buffer<V: VertexLayout>(
         &mut self,
-        verticies: Vec<Vertex>,
-        indicies: Vec<u16>,
+        verticies: Vec<V>,
+        indicies: impl Into<Indices>,
     ) -> Result<VertexBuffers, anyhow::Error> {
+        self.build_vertex_buffer_from_bytes(bytemuck::cast_slice(verticies.as_slice()), indicies)
+    }
+
+    pub(crate) fn build_vertex_buffer_from_bytes(
+        &mut self,
+        verticies: &[u8],
+        indicies: impl Into<Indices>,
+    ) -> Result<VertexBuffers, anyhow::Error> {
+        let indicies: Indices = indicies.into();
         let vertex_buffer = self
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(verticies.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX,
+                contents: verticies,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             });
 
         let index_buffer = self
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(indicies.as_slice()),
-                usage: wgpu::BufferUsages::INDEX,
+                contents: indicies.as_bytes(),
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
             });
 
         Ok(VertexBuffers {
             vertex_buffer,
             index_buffer,
             length: indicies.len() as u32,
+            index_format: indicies.format(),
+            capacity: (verticies.len(), indicies.as_bytes().len()),
+        })
+    }
+
+    /// Creates a new instance buffer, holding the transformation and color of each instance
+    pub fn build_instance_buffer(
+        &mut self,
+        instances: &[Instance],
+    ) -> Result<InstanceBuffers, anyhow::Error> {
+        let mut data: Vec<InstanceRaw> = instances.iter().map(|i| i.to_raw()).collect();
+        // empty buffers can't be bound, nothing is drawn from it anyway
+        if data.is_empty() {
+            data.push(Instance::default().to_raw());
+        }
+
+        let instance_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(data.as_slice()),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+
+        Ok(InstanceBuffers {
+            instance_buffer,
+            length: instances.len() as u32,
+            capacity: data.len(),
         })
     }
+
+    /// Writes the vertices and indices into the existing buffers. The buffers are rebuilt
+    /// only when the new data doesn't fit in them
+    pub fn update_vertex_buffer<V: VertexLayout>(
+        &mut self,
+        vertex_buffer: &mut VertexBuffers,
+        verticies: &[V],
+        indicies: &Indices,
+    ) -> Result<(), anyhow::Error> {
+        self.update_vertex_buffer_from_bytes(
+            vertex_buffer,
+            bytemuck::cast_slice(verticies),
+            indicies,
+        )
+    }
+
+    pub(crate) fn update_vertex_buffer_from_bytes(
+        &mut self,
+        vertex_buffer: &mut VertexBuffers,
+        verticies: &[u8],
+        indicies: &Indices,
+    ) -> Result<(), anyhow::Error> {
+        if verticies.len() > vertex_buffer.capacity.0
+            || indicies.as_bytes().len() > vertex_buffer.capacity.1
+        {
+            *vertex_buffer = self.build_vertex_buffer_from_bytes(verticies, indicies.clone())?;
+            return Ok(());
+        }
+
+        // writes have to be a multiple of 4 bytes, the buffers were padded to that when built
+        self.queue.write_buffer(
+            &vertex_buffer.vertex_buffer,
+            0,
+            &pad_to_copy_alignment(verticies),
+        );
+        self.queue.write_buffer(
+            &vertex_buffer.index_buffer,
+            0,
+            &pad_to_copy_alignment(indicies.as_bytes()),
+        );
+        vertex_buffer.length = indicies.len() as u32;
+        vertex_buffer.index_format = indicies.format();
+
+        Ok(())
+    }
+
+    /// Writes the instances into the existing instance buffer, rebuilding it only when they don't fit
+    pub fn update_instance_buffer(
+        &mut self,
+        instance_buffer: &mut InstanceBuffers,
+        instances: &[Instance],
+    ) -> Result<(), anyhow::Error> {
+        if instances.len() > instance_buffer.capacity {
+            *instance_buffer = self.build_instance_buffer(instances)?;
+            return Ok(());
+        }
+
+        let data: Vec<InstanceRaw> = instances.iter().map(|i| i.to_raw()).collect();
+        if !data.is_empty() {
+            self.queue.write_buffer(
+                &instance_buffer.instance_buffer,
+                0,
+                bytemuck::cast_slice(data.as_slice()),
+            );
+        }
+        instance_buffer.length = instances.len() as u32;
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::NotFound(path) => write!(f, "{} doesn't exist", path.display()),
+            TextureError::Io(error) => write!(f, "couldn't read the file: {}", error),
+            TextureError::UnsupportedFormat(error) => {
+                write!(f, "the image format isn't supported: {}", error)
+            }
+            TextureError::Decode(error) => write!(f, "the image is corrupt: {}", error),
+            TextureError::TooLarge {
+                width,
+                height,
+                max_dimension,
+            } => write!(
+                f,
+                "the image is {}x{}, but the device supports at most {}x{}",
+                width, height, max_dimension, max_dimension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl TextureMode {
+    pub(crate) fn address_mode(self) -> wgpu::AddressMode {
+        match self {
+            TextureMode::Clamp => wgpu::AddressMode::ClampToEdge,
+            TextureMode::Repeat => wgpu::AddressMode::Repeat,
+            TextureMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+            TextureMode::Border => wgpu::AddressMode::ClampToBorder,
+        }
+    }
+}
+
+/// Pads the data with zeros to a size that can be written into a buffer
+fn pad_to_copy_alignment(data: &[u8]) -> std::borrow::Cow<'_, [u8]> {
+    let alignment = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+    if data.len() % alignment == 0 {
+        return std::borrow::Cow::Borrowed(data);
+    }
+
+    let mut padded = data.to_vec();
+    padded.resize((data.len() + alignment - 1) / alignment * alignment, 0);
+    std::borrow::Cow::Owned(padded)
 }
@@ -20,30 +20,209 @@ pub struct Vertex {
     /// Contains the normal face of the vertex
     pub normal: [f32; 3],
 }
-impl Vertex {
-    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+impl VertexLayout for Vertex {
+    fn attributes() -> Vec<(&'static str, wgpu::VertexFormat)> {
+        vec![
+            ("position", wgpu::VertexFormat::Float32x3),
+            ("texture_coordinates", wgpu::VertexFormat::Float32x2),
+            ("normal", wgpu::VertexFormat::Float32x3),
+        ]
+    }
+}
+
+/// A vertex type that can be sent to the GPU. It can be implemented for your own `#[repr(C)]`
+/// Pod structs, to add vertex colors, tangents, more uv sets, skinning weights, ...
+///
+/// The `ShaderBuilder` uses the `position`, `texture_coordinates` and `color` attributes if they exist.
+pub trait VertexLayout: bytemuck::Pod {
+    /// The name and format of each field, in the order of the struct. The names are used
+    /// for the fields of the `VertexInput` in the shader
+    fn attributes() -> Vec<(&'static str, wgpu::VertexFormat)>;
+
+    /// The layout of the vertex buffer, with the attributes at shader locations starting from 0
+    fn layout() -> VertexLayoutDescriptor {
+        VertexLayoutDescriptor::new(
+            std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            Self::attributes(),
+        )
+    }
+}
+
+/// The layout of a vertex buffer, describing where each attribute is within a vertex
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VertexLayoutDescriptor {
+    /// Size of a single vertex in bytes
+    pub array_stride: wgpu::BufferAddress,
+    /// The attributes, placed one after another at shader locations starting from 0
+    pub attributes: Vec<wgpu::VertexAttribute>,
+    /// The name of each attribute in the shader
+    pub names: Vec<&'static str>,
+}
+impl VertexLayoutDescriptor {
+    /// Creates a layout with the attributes placed one after another, without padding
+    pub fn new(
+        array_stride: wgpu::BufferAddress,
+        attributes: Vec<(&'static str, wgpu::VertexFormat)>,
+    ) -> Self {
+        let mut offset = 0;
+        let mut names = Vec::with_capacity(attributes.len());
+        let attributes = attributes
+            .into_iter()
+            .enumerate()
+            .map(|(location, (name, format))| {
+                let attribute = wgpu::VertexAttribute {
+                    offset,
+                    shader_location: location as u32,
+                    format,
+                };
+                offset += format.size();
+                names.push(name);
+                attribute
+            })
+            .collect();
+
+        Self {
+            array_stride,
+            attributes,
+            names,
+        }
+    }
+
+    /// The layout as used by the pipeline
+    pub fn desc(&self) -> wgpu::VertexBufferLayout<'_> {
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            array_stride: self.array_stride,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
+            attributes: &self.attributes,
+        }
+    }
+
+    /// Returns the format of the attribute with the given name
+    pub fn format_of(&self, name: &str) -> Option<wgpu::VertexFormat> {
+        self.names
+            .iter()
+            .position(|i| *i == name)
+            .map(|i| self.attributes[i].format)
+    }
+
+    /// The first shader location after the attributes, where the instance attributes start
+    pub fn next_location(&self) -> u32 {
+        self.attributes.len() as u32
+    }
+}
+
+/// The indices of a mesh. 16 bit indices take half the memory, but can only point to the
+/// first 65,536 vertices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl Indices {
+    /// Amount of indices
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    /// Returns true if there are no indices
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The index format the draw calls have to use for these indices
+    pub fn format(&self) -> wgpu::IndexFormat {
+        match self {
+            Indices::U16(_) => wgpu::IndexFormat::Uint16,
+            Indices::U32(_) => wgpu::IndexFormat::Uint32,
+        }
+    }
+
+    /// Iterates over the indices, widened to u32
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Indices::U16(indices) => Box::new(indices.iter().map(|i| *i as u32)),
+            Indices::U32(indices) => Box::new(indices.iter().copied()),
         }
     }
+
+    /// The raw bytes of the indices as they are sent to the GPU
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Indices::U16(indices) => bytemuck::cast_slice(indices.as_slice()),
+            Indices::U32(indices) => bytemuck::cast_slice(indices.as_slice()),
+        }
+    }
+}
+impl From<Vec<u16>> for Indices {
+    fn from(indices: Vec<u16>) -> Self {
+        Indices::U16(indices)
+    }
+}
+impl From<Vec<u32>> for Indices {
+    fn from(indices: Vec<u32>) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+/// A single copy of an instanced object, with its own transformation and color
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    /// Applied on top of the object's own transformation
+    pub transformation_matrix: nalgebra_glm::Mat4,
+    /// Multiplied with the object's color
+    pub color: uniform_type::Array4,
+}
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            transformation_matrix: nalgebra_glm::identity(),
+            color: uniform_type::Array4 {
+                data: [1f32, 1f32, 1f32, 1f32],
+            },
+        }
+    }
+}
+impl Instance {
+    /// Creates an instance moved by the given amount, relative to the object
+    pub fn new(position: (f32, f32, f32), color: [f32; 4]) -> Self {
+        Self {
+            transformation_matrix: nalgebra_glm::translation(&nalgebra_glm::vec3(
+                position.0, position.1, position.2,
+            )),
+            color: uniform_type::Array4 { data: color },
+        }
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            transformation_matrix: self.transformation_matrix.into(),
+            color: self.color.data,
+        }
+    }
+}
+
+/// The per instance data as it's laid out in the instance buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub transformation_matrix: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+impl InstanceRaw {
+    /// The instance attributes, starting at the given shader location. The matrix takes one
+    /// location per column, followed by the color
+    pub(crate) fn attributes(first_location: u32) -> Vec<wgpu::VertexAttribute> {
+        (0..5)
+            .map(|i| wgpu::VertexAttribute {
+                offset: (i * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+                shader_location: first_location + i as u32,
+                format: wgpu::VertexFormat::Float32x4,
+            })
+            .collect()
+    }
 }
 
 /// Objects make it easier to work with Blue Engine, it automates most of work needed for
@@ -56,7 +235,13 @@ pub struct Object {
     /// A list of Vertex
     pub vertices: Vec<Vertex>,
     /// A list of indices that dictates the order that vertices appear
-    pub indices: Vec<u16>,
+    pub indices: Indices,
+    /// The copies of the object that are drawn, all of them in a single draw call
+    pub instances: Vec<Instance>,
+    /// The layout of the vertices of the object
+    pub vertex_layout: VertexLayoutDescriptor,
+    // the vertices of objects with a custom vertex type, as raw bytes. Used instead of `vertices`
+    pub(crate) custom_vertices: Option<Vec<u8>>,
     pub uniform_layout: wgpu::BindGroupLayout,
     /// Pipeline holds all the data that is sent to GPU, including shaders and textures
     pub pipeline: Pipeline,
@@ -65,8 +250,8 @@ pub struct Object {
     pub scale: (f32, f32, f32),
     /// Dictates the position of your object in pixels
     pub position: (f32, f32, f32),
-    // flags the object to be updated until next frame
-    pub(crate) changed: bool,
+    // flags the parts of the object to be updated until next frame
+    pub(crate) changed: ObjectChanges,
     /// Transformation matrix helps to apply changes to your object, including position, orientation, ...
     /// Best choice is to let the Object system handle it
     pub transformation_matrix: nalgebra_glm::Mat4,
@@ -80,12 +265,48 @@ pub struct Object {
     pub shader_builder: crate::objects::ShaderBuilder,
     /// Shader settings
     pub shader_settings: ShaderSettings,
+    /// Objects on higher layers are drawn on top of the lower layers, regardless of their depth
+    pub layer: i32,
+    /// The draw order within the layer, objects with higher index are drawn later
+    pub z_index: i32,
+    // the order the object was created in, keeps the draw order stable within a layer
+    pub(crate) creation_order: u64,
     /// Camera have any effect on the object?
     pub camera_effect: bool,
+    /// Does it cast shadows from lights? Only objects affected by the camera cast shadows
+    pub cast_shadows: bool,
+    /// Is it shadowed by other objects, when it's lit by a `LightManager`?
+    pub receive_shadows: bool,
+    /// The physically based material, used instead of the texture when lit by a `LightManager`
+    pub material: Option<MaterialHandle>,
+    /// The region of the texture the uvs of the vertices are remapped to, set by `Object::set_sprite`
+    pub uv_region: Option<AtlasRegion>,
     /// Uniform Buffers to be sent to GPU
     pub uniform_buffers: Vec<wgpu::Buffer>,
 }
 
+/// The parts of an object that have changed since they were last sent to the GPU
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ObjectChanges {
+    pub(crate) transform: bool,
+    pub(crate) color: bool,
+    pub(crate) vertices: bool,
+    pub(crate) instances: bool,
+    pub(crate) shader: bool,
+}
+impl ObjectChanges {
+    /// Every part flagged as changed
+    pub(crate) fn all() -> Self {
+        Self {
+            transform: true,
+            color: true,
+            vertices: true,
+            instances: true,
+            shader: true,
+        }
+    }
+}
+
 /// Extra settings to customize objects on time of creation
 #[derive(Debug, Clone, Copy)]
 pub struct ObjectSettings {
@@ -102,6 +323,14 @@ pub struct ObjectSettings {
     pub camera_effect: bool,
     /// Shader Settings
     pub shader_settings: ShaderSettings,
+    /// The render layer, higher layers are drawn on top of the lower ones
+    pub layer: i32,
+    /// The draw order within the layer
+    pub z_index: i32,
+    /// Does it cast shadows from lights?
+    pub cast_shadows: bool,
+    /// Is it shadowed by other objects, when it's lit by a `LightManager`?
+    pub receive_shadows: bool,
 }
 impl Default for ObjectSettings {
     fn default() -> Self {
@@ -115,6 +344,10 @@ impl Default for ObjectSettings {
             },
             camera_effect: true,
             shader_settings: ShaderSettings::default(),
+            layer: 0,
+            z_index: 0,
+            cast_shadows: true,
+            receive_shadows: true,
         }
     }
 }
@@ -156,9 +389,9 @@ pub trait EnginePlugin {
 ///     let engine = Engine::new(WindowDescriptor::default()).expect("Couldn't create the engine");
 /// }
 /// ```
-/// The WindowDescriptor simply holds what features you would like for your window. If you are reading this on later version of
-/// the engine, you might be able to even run the engine in headless mode meaning there would not be a need for a window and the
-/// renders would come as image files.
+/// The WindowDescriptor simply holds what features you would like for your window. The engine can also run in headless mode
+/// through `Engine::new_headless`, meaning there would not be a need for a window and the frames are rendered to an offscreen
+/// texture, one `render_frame` call at a time.
 ///
 /// If you so wish to have a window, you would need to start a window update loop. The update loop of window runs a frame every few milisecond,
 /// and gives you details of what is happening during this time, like input events. You can also modify existing parts of the engine during
@@ -173,9 +406,11 @@ pub struct Engine {
     /// The renderer does exactly what it is called. It works with the GPU to render frames according to the data you gave it.
     pub renderer: Renderer,
     // The event_loop handles the events of the window and inputs, so it's used internally
-    pub(crate) event_loop: winit::event_loop::EventLoop<()>,
+    pub(crate) event_loop: Option<winit::event_loop::EventLoop<()>>,
     /// The window handles everything about window and inputs. This includes ability to modify window and listen to input devices for changes.
-    pub window: winit::window::Window,
+    ///
+    /// Headless engines do not have a window.
+    pub window: Option<winit::window::Window>,
     /// The object system is a way to make it easier to work with the engine. Obviously you can work without it, but it's for those who
     /// do not have the know-how, or wish to handle all the work of rendering data manually.
     pub objects: std::collections::HashMap<&'static str, Object>,
@@ -191,6 +426,7 @@ pub struct Pipeline {
     pub vertex_buffer: VertexBuffers,
     pub texture: Textures,
     pub uniform: Option<UniformBuffers>,
+    pub instance_buffer: InstanceBuffers,
 }
 
 /// Container for vertex and index buffer
@@ -200,11 +436,29 @@ pub struct VertexBuffers {
     /// An array of indices. Indices are a way to reuse vertices, this in turn helps greatly in reduction of amount of vertices needed to be sent to the GPU
     pub index_buffer: wgpu::Buffer,
     pub length: u32,
+    /// The format of the indices in the index buffer
+    pub index_format: wgpu::IndexFormat,
+    // amount of vertex and index bytes the buffers can hold, before they have to be rebuilt
+    pub(crate) capacity: (usize, usize),
+}
+
+/// Container for the instance buffer of an object
+pub struct InstanceBuffers {
+    /// Transformation and color of each instance
+    pub instance_buffer: wgpu::Buffer,
+    /// Amount of instances in the buffer
+    pub length: u32,
+    // amount of instances the buffer can hold, before it has to be rebuilt
+    pub(crate) capacity: usize,
 }
 
 // Main renderer class. this will contain all methods and data related to the renderer
 pub struct Renderer {
     pub surface: Option<wgpu::Surface>,
+    /// The color target frames are rendered to when the engine is headless
+    pub headless_target: Option<(wgpu::Texture, wgpu::TextureView)>,
+    /// A copy of the last presented frame, kept while frame capture is enabled on a windowed renderer
+    pub(crate) frame_capture_target: Option<(wgpu::Texture, wgpu::TextureView)>,
     #[cfg(feature = "android")]
     pub instance: wgpu::Instance,
     #[allow(unused)]
@@ -216,13 +470,336 @@ pub struct Renderer {
     pub texture_bind_group_layout: wgpu::BindGroupLayout,
     pub default_uniform_bind_group_layout: wgpu::BindGroupLayout,
     pub depth_buffer: (wgpu::Texture, wgpu::TextureView, wgpu::Sampler),
+    /// The number of samples per pixel, for MSAA
+    pub(crate) sample_count: u32,
+    /// The multisampled color target that is resolved to the frame, when MSAA is enabled
+    pub(crate) msaa_buffer: Option<(wgpu::Texture, wgpu::TextureView)>,
     pub default_data: Option<(Textures, Shaders, UniformBuffers)>,
+    /// What the frames show behind the objects
+    pub(crate) background: Background,
+    pub(crate) background_data: Option<(Shaders, wgpu::Buffer, UniformBuffers)>,
+    // amount of objects built so far, used for their creation order
+    pub(crate) object_count: u64,
+    /// Pipelines built for objects, shared between the ones with the same shader and settings
+    pub pipeline_cache: PipelineCache,
     pub camera: Option<UniformBuffers>,
-    pub custom_render_pass:
-        Option<Box<dyn FnMut(&mut wgpu::CommandEncoder, &wgpu::TextureView) + 'static>>,
+    /// The passes that render each frame, starting with the scene
+    pub render_graph: RenderGraph,
+    /// Fullscreen effects applied in order after the scene is drawn
+    pub post_processing: Vec<PostEffect>,
+    pub(crate) post_processing_data: Vec<PostEffectData>,
+    /// Renders the scene in HDR when set
+    pub(crate) hdr: Option<HdrSettings>,
+    /// The layout of the lights and shadow maps, bound at group 3 by shaders with `ShaderSettings::lighting`
+    pub lighting_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) shadow_maps: ShadowMaps,
+    /// The layout of materials, bound at group 0 by shaders with `ShaderSettings::material`
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) materials: Vec<Material>,
+    /// The layout of cubemaps, a cube texture at binding 0 and its sampler at binding 1
+    pub cubemap_bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) skybox_data: Option<(Shaders, wgpu::Buffer, UniformBuffers)>,
+}
+
+/// Keeps the render pipelines of objects, so they are only built once for each shader and settings
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    pub(crate) pipelines: std::collections::HashMap<PipelineKey, Shaders>,
+    /// Amount of times a pipeline was reused from the cache
+    pub hits: u64,
+    /// Amount of times a pipeline had to be built
+    pub misses: u64,
+}
+impl PipelineCache {
+    /// Amount of pipelines in the cache
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    /// Returns true if there are no pipelines in the cache
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Removes all the pipelines. Objects keep using the ones they already have
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+/// Everything that makes a pipeline different from another
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    pub(crate) shader_source: String,
+    pub(crate) settings: ShaderSettings,
+    /// Stands in for the uniform layout, which is the same for the same count when built
+    /// by `build_uniform_buffer`
+    pub(crate) uniform_count: usize,
+    pub(crate) vertex_layout: VertexLayoutDescriptor,
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) sample_count: u32,
+}
+
+/// Decides what the frames show behind the objects
+#[derive(Debug)]
+pub enum Background {
+    /// Clears the frame with a single color
+    Color([f32; 4]),
+    /// Keeps what was drawn on the previous frames, for trail and accumulation effects.
+    ///
+    /// A window swaps between a few textures, so the kept content there can be a few frames old.
+    DontClear,
+    /// A vertical gradient going from the top color to the bottom color
+    Gradient {
+        /// Color at the top of the frame
+        top: [f32; 4],
+        /// Color at the bottom of the frame
+        bottom: [f32; 4],
+    },
+    /// Stretches a texture over the whole frame
+    Texture(Textures),
+    /// A cubemap around the camera, turning with its rotation but never getting closer
+    Skybox(Cubemap),
+}
+impl Default for Background {
+    fn default() -> Self {
+        Self::Color([0f32, 0f32, 0f32, 1f32])
+    }
+}
+
+/// A fullscreen effect applied to the frame after the scene is drawn. The effects of
+/// `Renderer::post_processing` are applied in order, and their parameters can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+    /// Makes bright parts of the frame glow into their surroundings
+    Bloom {
+        /// How bright a color has to be to glow
+        threshold: f32,
+        /// How strong the glow is
+        intensity: f32,
+        /// How far the glow spreads, in pixels
+        radius: f32,
+    },
+    /// Maps the colors of the frame into the displayable range
+    Tonemapping {
+        /// The curve used to map the colors
+        operator: TonemapOperator,
+        /// The colors are multiplied by this before they are mapped
+        exposure: f32,
+    },
+    /// Fast approximate anti-aliasing, smoothing edges without multisampling
+    Fxaa {
+        /// The contrast, relative to the brightest neighbor, needed for a pixel to be smoothed
+        edge_threshold: f32,
+        /// The contrast below which dark pixels are never smoothed
+        edge_threshold_min: f32,
+    },
+    /// Darkens the corners of the frame
+    Vignette {
+        /// How dark the corners get, between 0 and 1
+        intensity: f32,
+        /// The distance from the center where the darkening starts, with 1 being the corners
+        radius: f32,
+        /// How gradually the darkening is faded in
+        smoothness: f32,
+    },
+    /// Adjusts the colors of the frame
+    ColorGrading {
+        /// Added to every color
+        brightness: f32,
+        /// Spreads the colors away from middle gray, 1 keeps them as they are
+        contrast: f32,
+        /// 0 makes the frame grayscale, 1 keeps the colors as they are
+        saturation: f32,
+        /// Multiplied with every color
+        tint: [f32; 3],
+    },
+}
+
+/// The curve tonemapping uses to map bright colors into the displayable range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TonemapOperator {
+    /// Simple and soft, keeps the colors but flattens the highlights
+    Reinhard,
+    /// The filmic curve of the Academy Color Encoding System, with more contrast
+    Aces,
+}
+
+/// Settings of HDR rendering. The scene is rendered with colors beyond 1.0, which are mapped
+/// to the range of the frame at the end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HdrSettings {
+    /// The curve used to map the colors to the frame
+    pub operator: TonemapOperator,
+    /// The colors are multiplied by this before they are mapped, higher values brighten the frame
+    pub exposure: f32,
+}
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            operator: TonemapOperator::Aces,
+            exposure: 1.0,
+        }
+    }
+}
+
+/// The GPU side of a post effect
+pub(crate) struct PostEffectData {
+    pub(crate) effect: std::mem::Discriminant<PostEffect>,
+    // the format of the texture the effect is drawn to
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) pipeline: Shaders,
+    // drawn one after another at half resolution before the effect, which reads the last of them
+    pub(crate) stages: Vec<Shaders>,
+    pub(crate) uniform_buffer: wgpu::Buffer,
+    pub(crate) uniform: UniformBuffers,
 }
 
-/// Descriptor and settings for a window.
+/// The shadow maps of the lights that cast shadows, and everything needed to render them
+pub(crate) struct ShadowMaps {
+    /// A depth texture with a layer for each cascade or cube face, of one light after another
+    pub(crate) texture: wgpu::Texture,
+    // a view of each layer, to render the casters into
+    pub(crate) layers: Vec<wgpu::TextureView>,
+    pub(crate) resolution: u32,
+    pub(crate) sampler: wgpu::Sampler,
+    pub(crate) uniform_buffer: wgpu::Buffer,
+    /// The lights shining on the lit objects, bound along with the shadow maps
+    pub(crate) light_buffer: wgpu::Buffer,
+    /// Amount of lights the light buffer has room for
+    pub(crate) max_lights: usize,
+    /// The texture, sampler and uniforms for the shaders receiving shadows, and the lights
+    pub(crate) bind_group: UniformBuffers,
+    /// The view projection of each layer, for rendering the casters
+    pub(crate) layer_uniforms: Vec<(wgpu::Buffer, UniformBuffers)>,
+    /// Amount of layers rendered each frame, 0 when no light casts shadows
+    pub(crate) active_layers: usize,
+    /// The objects drawn into the shadow maps
+    pub(crate) casters: Vec<&'static str>,
+    /// The lights with shadow settings over `shadows::MAX_SHADOW_CASTERS`, last warned about
+    pub(crate) ignored_casters: Vec<&'static str>,
+    /// Depth only pipelines for the casters, by their vertex layout, uniform count and topology
+    pub(crate) pipelines: std::collections::HashMap<
+        (
+            VertexLayoutDescriptor,
+            usize,
+            ShaderPrimitive,
+            Option<IndexFormat>,
+        ),
+        Shaders,
+    >,
+}
+
+/// A texture the scene can be rendered into instead of the window, e.g. from a second camera.
+/// Its color texture can then be used as the texture of other objects, for screens, mirrors, or minimaps.
+pub struct RenderTarget {
+    /// The color texture that is rendered to
+    pub color: (wgpu::Texture, wgpu::TextureView),
+    /// The depth buffer used while rendering to the target
+    pub depth: (wgpu::Texture, wgpu::TextureView, wgpu::Sampler),
+    /// The multisampled color texture that is resolved to the color texture, when MSAA is enabled
+    pub msaa: Option<(wgpu::Texture, wgpu::TextureView)>,
+    /// The size of the target in pixels, as width and height
+    pub size: (u32, u32),
+    /// The color format of the target, same as the one objects are rendered with
+    pub format: wgpu::TextureFormat,
+}
+
+/// A set of named render passes, that are run in the order of their dependencies every frame.
+///
+/// Passes write to and read from named textures. A pass that reads a texture runs after all the
+/// passes that write to it, and passes that write to the same texture run in the order they were added.
+/// The frame and its depth buffer are available as `RenderGraph::FRAME` and `RenderGraph::DEPTH`, and any
+/// other texture is allocated by the graph, and resized along with the frame.
+///
+/// The scene itself is drawn by the `RenderGraph::SCENE` pass, which can be replaced or redirected.
+#[derive(Default)]
+pub struct RenderGraph {
+    pub(crate) passes: Vec<RenderGraphPass>,
+    pub(crate) descriptors: std::collections::HashMap<RenderGraphName, RenderGraphTexture>,
+    // textures allocated for the passes, rebuilt when their size or format no longer match
+    pub(crate) textures: std::collections::HashMap<RenderGraphName, RenderGraphTextureData>,
+}
+
+/// Records a pass of the render graph into the encoder of the context
+pub type RenderGraphExecute = Box<dyn FnMut(&mut RenderGraphContext) -> anyhow::Result<()>>;
+
+/// A named render pass of the render graph, along with the textures it reads and writes
+pub struct RenderGraphPass {
+    /// The name of the pass, unique within the graph
+    pub name: RenderGraphName,
+    /// The textures the pass reads, color or depth
+    pub inputs: Vec<RenderGraphName>,
+    /// The color textures the pass writes to
+    pub color_outputs: Vec<RenderGraphName>,
+    /// The depth texture the pass writes to
+    pub depth_output: Option<RenderGraphName>,
+    /// Records the pass. Only the declared textures can be accessed through the context
+    pub execute: RenderGraphExecute,
+}
+
+/// Describes a texture allocated by the render graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderGraphTexture {
+    /// The format of the texture. Defaults to `Renderer::render_format()` for color textures,
+    /// which is `Renderer::HDR_FORMAT` when HDR is enabled, and to `Renderer::DEPTH_FORMAT`
+    /// for depth textures
+    pub format: Option<wgpu::TextureFormat>,
+    /// The size of the texture
+    pub size: RenderGraphTextureSize,
+    /// Renders to a multisampled texture with the sample count of the renderer, that is resolved
+    /// to the texture at the end of the pass. Needed when the objects are drawn into it with MSAA on
+    pub multisampled: bool,
+}
+impl Default for RenderGraphTexture {
+    fn default() -> Self {
+        Self {
+            format: None,
+            size: RenderGraphTextureSize::Frame,
+            multisampled: false,
+        }
+    }
+}
+
+/// The size of a texture allocated by the render graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderGraphTextureSize {
+    /// Same size as the frame
+    Frame,
+    /// The size of the frame multiplied by the scale, e.g. 0.5 for half resolution
+    Scaled(f32),
+    /// A fixed size in pixels, as width and height
+    Fixed(u32, u32),
+}
+
+/// A texture allocated by the render graph
+pub(crate) struct RenderGraphTextureData {
+    pub(crate) texture: (wgpu::Texture, wgpu::TextureView),
+    pub(crate) msaa: Option<(wgpu::Texture, wgpu::TextureView)>,
+    // to sample color textures in shaders, through the texture bind group layout
+    pub(crate) bind_group: Option<Textures>,
+    pub(crate) size: (u32, u32),
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) sample_count: u32,
+}
+
+/// What a render pass of the render graph gets to record its commands
+pub struct RenderGraphContext<'a> {
+    /// The encoder the pass records into
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub renderer: &'a Renderer,
+    pub objects: &'a std::collections::HashMap<&'static str, Object>,
+    pub camera: &'a Camera,
+    pub(crate) pass: &'a str,
+    pub(crate) reads: &'a [RenderGraphName],
+    pub(crate) color_outputs: &'a [RenderGraphName],
+    pub(crate) depth_output: Option<&'a str>,
+    pub(crate) frame: &'a wgpu::TextureView,
+    pub(crate) textures: &'a std::collections::HashMap<RenderGraphName, RenderGraphTextureData>,
+}
+
+/// Descriptor and settings for a window. Fields added in later versions have defaults,
+/// so literals filling the rest with `..Default::default()` keep working.
 #[derive(Debug, Clone, Copy)]
 pub struct WindowDescriptor {
     /// The width of the window
@@ -237,6 +814,9 @@ pub struct WindowDescriptor {
     pub resizable: bool,
     /// Define how much power should the app ask for
     pub power_preference: PowerPreference,
+    /// The number of samples per pixel for multisample anti-aliasing (MSAA). 1 disables it,
+    /// and 4 is supported everywhere
+    pub sample_count: u32,
 }
 impl std::default::Default for WindowDescriptor {
     /// Will quickly create a window with default settings
@@ -248,6 +828,7 @@ impl std::default::Default for WindowDescriptor {
             decorations: true,
             resizable: true,
             power_preference: PowerPreference::LowPower,
+            sample_count: 1,
         }
     }
 }
@@ -273,14 +854,87 @@ pub struct Camera {
     // For checking and rebuilding it's uniform buffer
     pub(crate) changed: bool,
     pub(crate) uniform_data: UniformBuffers,
+    // the buffer behind the uniform data, written in place when the camera changes
+    pub(crate) uniform_buffer: wgpu::Buffer,
     pub(crate) add_position_and_target: bool,
 }
 
+/// Lights the objects with the lights set on it, and ambient light.
+///
+/// The lights share a uniform buffer with room for `max_lights` of them, and
+/// `LightManager::update` fails when more shine at once. Lit objects need a `normal`
+/// attribute of three floats in their vertex layout
 pub struct LightManager {
     pub ambient_color: uniform_type::Array4,
     pub ambient_strength: f32,
+    /// The most lights that can shine at once, `utils::light::DEFAULT_MAX_LIGHTS` (16) unless
+    /// changed. The size of a uniform buffer limits it, to 255 lights on the smallest devices
+    pub max_lights: usize,
     pub affected_objects: Vec<&'static str>,
     pub light_objects: std::collections::BTreeMap<&'static str, ([f32; 3], uniform_type::Array4)>,
+    /// How each light shines and casts shadows, by the name of its object
+    pub lights: std::collections::BTreeMap<&'static str, Light>,
+}
+
+/// A light, shining from the position of its object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    /// The shape of the light
+    pub kind: LightKind,
+    /// The color of the light, regardless of the color of its object
+    pub color: [f32; 3],
+    /// How bright the light is. Point and spot lights fall off with the square of the distance,
+    /// shining with this intensity at a distance of 1
+    pub intensity: f32,
+    /// How far point and spot lights reach, they fade out smoothly before it
+    pub range: f32,
+    /// The shadows cast by the light, none when `None`
+    pub shadows: Option<ShadowSettings>,
+}
+
+/// The shape of a light
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LightKind {
+    /// Lights everything from the same direction, like the sun. The shadows are split into
+    /// cascades along the view of the camera, so that nearby shadows get the most detail
+    Directional {
+        /// The direction the light travels in
+        direction: [f32; 3],
+    },
+    /// Shines in every direction, casting shadows all around
+    #[default]
+    Point,
+    /// Shines in a cone around a direction
+    Spot {
+        /// The direction the cone points to
+        direction: [f32; 3],
+        /// The angle between the direction and the edge of the cone, in radians
+        angle: f32,
+    },
+}
+
+/// How a light casts shadows.
+///
+/// Up to `shadows::MAX_SHADOW_CASTERS` lights can cast shadows at once, in the order of their
+/// names, and the ones after them are lit without shadows. Their shadow maps share a texture,
+/// so all of them take the largest `resolution` among them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// The width and height of each shadow map in texels
+    pub resolution: u32,
+    /// Amount of cascades of a directional light, from 1 to 4
+    pub cascades: u32,
+    /// How far from the camera the shadows of a directional light reach, and how far from
+    /// the light the shadows of point and spot lights reach
+    pub distance: f32,
+    /// Subtracted from the depth of surfaces when they are compared with the shadow map,
+    /// against surfaces shadowing themselves
+    pub bias: f32,
+    /// Moves surfaces along their normal before they are compared, in world units.
+    /// Helps with surfaces at a steep angle to the light, where the bias isn't enough
+    pub normal_bias: f32,
+    /// The radius of the PCF kernel in texels, 0 gives hard edges
+    pub pcf_radius: u32,
 }
 
 /// This function helps in converting pixel value to the value that is between -1 and +1
@@ -329,6 +983,214 @@ pub enum TextureMode {
     Repeat,
     /// Repeats the texture, but mirrors it on edges
     MirrorRepeat,
+    /// Fills everything beyond the edges with the border color of the sampler.
+    /// Needs a device with `Features::ADDRESS_MODE_CLAMP_TO_BORDER`
+    Border,
+}
+
+/// How a texture is created, with `Renderer::build_texture_with_settings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureSettings {
+    /// How the texture is filtered and how it looks beyond its borders
+    pub sampler: SamplerSettings,
+    /// The smaller copies of the texture, sampled when it's seen from afar
+    pub mipmaps: Mipmaps,
+    /// Uses the default texture with a warning when the texture can't be loaded,
+    /// instead of returning the error
+    pub fallback: bool,
+}
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self {
+            sampler: SamplerSettings::default(),
+            mipmaps: Mipmaps::Full,
+            fallback: false,
+        }
+    }
+}
+
+/// Why a texture couldn't be loaded, returned by `Renderer::build_texture` and can be
+/// found with `anyhow::Error::downcast_ref`
+#[derive(Debug)]
+pub enum TextureError {
+    /// There is no file at the path of the texture
+    NotFound(std::path::PathBuf),
+    /// The file of the texture couldn't be read
+    Io(std::io::Error),
+    /// The data isn't in an image format that can be decoded
+    UnsupportedFormat(String),
+    /// The data is in a known format, but it's corrupt
+    Decode(String),
+    /// The image is larger than the textures the device supports
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+    },
+}
+
+/// A texture of six square faces, sampled by direction. Created by `Renderer::build_cubemap`
+/// or `Renderer::build_cubemap_from_equirectangular`
+#[derive(Debug, Clone)]
+pub struct Cubemap {
+    /// The faces and their sampler, in the layout of `Renderer::cubemap_bind_group_layout`
+    pub bind_group: std::sync::Arc<wgpu::BindGroup>,
+    /// The width and height of each face in pixels
+    pub size: u32,
+}
+
+/// A named rectangle of a `TextureAtlas`, in texture coordinates from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    /// The left edge of the region
+    pub x: f32,
+    /// The top edge of the region
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Many images in a single texture, with their regions addressed by name. Created by
+/// `Renderer::build_texture_atlas` or `Renderer::load_sprite_sheet`
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    /// The texture all the images are in, shared by the objects showing them
+    pub texture: Textures,
+    /// The size of the texture in pixels
+    pub size: (u32, u32),
+    pub(crate) regions: std::collections::HashMap<String, AtlasRegion>,
+}
+
+/// How a texture is sampled. The default filters linearly within and between mip levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerSettings {
+    /// How the texture looks beyond its left and right edges
+    pub mode_u: TextureMode,
+    /// How the texture looks beyond its top and bottom edges
+    pub mode_v: TextureMode,
+    /// How the texture looks beyond its front and back, for textures with depth
+    pub mode_w: TextureMode,
+    /// The filter when the texture is drawn larger than its size
+    pub mag_filter: FilterMode,
+    /// The filter when the texture is drawn smaller than its size
+    pub min_filter: FilterMode,
+    /// The filter between mip levels
+    pub mipmap_filter: FilterMode,
+    /// The most samples taken for surfaces seen at a steep angle, a power of two up to 16.
+    /// 1 turns anisotropic filtering off, and it's ignored by devices without support for it
+    pub anisotropy: u8,
+    /// The color beyond the edges with `TextureMode::Border`
+    pub border_color: BorderColor,
+}
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            mode_u: TextureMode::Clamp,
+            mode_v: TextureMode::Clamp,
+            mode_w: TextureMode::Clamp,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy: 1,
+            border_color: BorderColor::TransparentBlack,
+        }
+    }
+}
+impl SamplerSettings {
+    /// Linear filtering with the same mode on every axis
+    pub fn with_mode(mode: TextureMode) -> Self {
+        Self {
+            mode_u: mode,
+            mode_v: mode,
+            mode_w: mode,
+            ..Default::default()
+        }
+    }
+
+    /// Nearest filtering everywhere, which keeps the texels sharp, e.g. for pixel art
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    /// Trilinear filtering with the given anisotropy, for textures seen at steep angles
+    /// such as floors
+    pub fn anisotropic(anisotropy: u8) -> Self {
+        Self {
+            anisotropy,
+            ..Default::default()
+        }
+    }
+}
+
+/// The mip chain of a texture. Each level is half the size of the one before, and the
+/// sampler blends between the two levels closest to the size the texture is drawn at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mipmaps {
+    /// Only the full size image, for textures drawn at their own size such as UI
+    None,
+    /// Every level down to a single texel
+    Full,
+    /// At most the given amount of levels, counting the full size image
+    Levels(u32),
+}
+impl Mipmaps {
+    /// The amount of levels of a texture of the given size
+    pub fn level_count(&self, width: u32, height: u32) -> u32 {
+        let full = 32 - width.max(height).max(1).leading_zeros();
+        match *self {
+            Self::None => 1,
+            Self::Full => full,
+            Self::Levels(levels) => levels.clamp(1, full),
+        }
+    }
+}
+
+/// A physically based material with the metallic-roughness model of glTF 2.0, kept by the
+/// renderer and shared by the objects using it
+#[derive(Debug)]
+pub(crate) struct Material {
+    pub(crate) name: &'static str,
+    pub(crate) bind_group: wgpu::BindGroup,
+}
+
+/// A handle to a material made with `Renderer::build_material`. Objects with the same
+/// material are drawn together, and changing the material changes all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialHandle(pub(crate) usize);
+
+/// The factors and maps of a `Material`. Each factor is multiplied with its map,
+/// and the maps left out are treated as white, or as a flat normal map
+#[derive(Debug, Clone)]
+pub struct MaterialSettings {
+    /// The color of the surface, in linear space
+    pub base_color: [f32; 4],
+    /// The color of the surface in sRGB, with the alpha channel as opacity
+    pub base_color_texture: Option<TextureData>,
+    /// 0 for dielectrics, 1 for metals
+    pub metallic: f32,
+    /// 0 for a mirror, 1 for a completely rough surface
+    pub roughness: f32,
+    /// Roughness in the green channel and metalness in the blue channel
+    pub metallic_roughness_texture: Option<TextureData>,
+    /// A tangent space normal map, with Y pointing along the V coordinate of the texture
+    pub normal_texture: Option<TextureData>,
+    /// Scales the X and Y of the normals of the normal map
+    pub normal_scale: f32,
+    /// Ambient occlusion in the red channel
+    pub occlusion_texture: Option<TextureData>,
+    /// How much of the occlusion is applied, from 0 to 1
+    pub occlusion_strength: f32,
+    /// The light given off by the surface, in linear space
+    pub emissive: [f32; 3],
+    /// The light given off by the surface in sRGB
+    pub emissive_texture: Option<TextureData>,
+    /// How every map of the material is sampled
+    pub sampler: SamplerSettings,
 }
 
 /// Defines file format of the texture to load
@@ -340,7 +1202,7 @@ pub enum TextureFormat {
 }
 
 // ? These definitions are taken from wgpu API docs
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ShaderSettings {
     // ===== PRIMITIVE ===== //
     /// The primitive topology used to interpret vertices
@@ -360,6 +1222,11 @@ pub struct ShaderSettings {
     /// Setting this to something other than `Fill` requires
     /// `NON_FILL_POLYGON_MODE` feature to be enabled
     pub polygon_mode: PolygonMode,
+    /// The comparison a fragment's depth has to pass against the depth buffer.
+    ///
+    /// When `None`, objects affected by the camera use `Less`, and objects without it use
+    /// `LessEqual`, so flat objects at the same depth stack in draw order
+    pub depth_compare: Option<CompareFunction>,
     /// If set to true, the polygon depth is clamped to 0-1
     /// range instead of being clipped.
     ///
@@ -376,7 +1243,9 @@ pub struct ShaderSettings {
 
     // ===== Multisample ===== //
     /// The number of samples calculated per pixel (for MSAA).
-    /// For non-multisampled textures, this should be `1`
+    ///
+    /// This is overridden by the renderer's sample count when the pipeline is
+    /// built, so that every pipeline matches the render targets
     pub count: u32,
     /// Bitmask that restricts the samples of a pixel modified
     /// by this pipeline. All samples can be enabled using the
@@ -391,6 +1260,16 @@ pub struct ShaderSettings {
     /// to be zero, and for alpha of one is guaranteed to be all
     /// 1-s.
     pub alpha_to_coverage_enabled: bool,
+
+    // ===== Blending ===== //
+    /// How the object is blended with what is already drawn behind it
+    pub blend_mode: BlendMode,
+
+    // ===== Lighting ===== //
+    /// Binds the lights and their shadow maps at group 3, for lit shaders. Requires a uniform layout
+    pub lighting: bool,
+    /// Binds a `Material` at group 0 instead of a texture
+    pub material: bool,
 }
 impl Default for ShaderSettings {
     fn default() -> Self {
@@ -400,11 +1279,85 @@ impl Default for ShaderSettings {
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: Some(wgpu::Face::Back),
             polygon_mode: wgpu::PolygonMode::Fill,
+            depth_compare: None,
             clamp_depth: false,
             conservative: false,
             count: 1,
             mask: !0,
             alpha_to_coverage_enabled: false,
+            blend_mode: BlendMode::Opaque,
+            lighting: false,
+            material: false,
+        }
+    }
+}
+
+impl ShaderSettings {
+    /// Fills in the depth comparison when it's left to the renderer
+    pub(crate) fn resolve_depth_compare(mut self, camera_effect: bool) -> Self {
+        self.depth_compare.get_or_insert(if camera_effect {
+            wgpu::CompareFunction::Less
+        } else {
+            wgpu::CompareFunction::LessEqual
+        });
+        self
+    }
+}
+
+/// Defines how the color of an object is combined with the colors behind it.
+///
+/// Objects that aren't opaque are drawn after the opaque ones, sorted back-to-front,
+/// and don't write to the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    /// Replaces the color behind, ignoring alpha
+    #[default]
+    Opaque,
+    /// Standard alpha blending, for colors that are not premultiplied
+    Alpha,
+    /// Adds the color on top of the one behind, scaled by alpha. Useful for glows and particles
+    Additive,
+    /// Alpha blending for colors that are already multiplied by their alpha
+    Premultiplied,
+    /// Multiplies the color behind with the object's color
+    Multiply,
+}
+impl BlendMode {
+    /// The blend state used by the pipeline for this mode
+    pub fn blend_state(&self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            BlendMode::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
         }
     }
+
+    /// Returns true if the mode blends with what is behind, and so needs sorting
+    pub fn is_transparent(&self) -> bool {
+        *self != BlendMode::Opaque
+    }
 }
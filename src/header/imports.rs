@@ -1,15 +1,20 @@
-/// Shaders are programs that runs on the GPU
-pub type Shaders = wgpu::RenderPipeline;
+/// Shaders are programs that runs on the GPU. They are shared between objects that use the same shader
+pub type Shaders = std::sync::Arc<wgpu::RenderPipeline>;
 /// Uniform Buffers are small amount of data that are sent from CPU to GPU
 pub type UniformBuffers = wgpu::BindGroup;
-/// Textures are image data that are sent to GPU to be set to a surface
-pub type Textures = wgpu::BindGroup;
+/// Textures are image data that are sent to GPU to be set to a surface. They are shared between objects that use the same texture
+pub type Textures = std::sync::Arc<wgpu::BindGroup>;
+/// The name of a pass or texture of the render graph, either a literal or made at runtime
+pub type RenderGraphName = std::borrow::Cow<'static, str>;
 
 pub type ShaderPrimitive = wgpu::PrimitiveTopology;
 pub type IndexFormat = wgpu::IndexFormat;
 pub type FrontFace = wgpu::FrontFace;
 pub type CullMode = wgpu::Face;
+pub type CompareFunction = wgpu::CompareFunction;
 pub type PolygonMode = wgpu::PolygonMode;
+pub type FilterMode = wgpu::FilterMode;
+pub type BorderColor = wgpu::SamplerBorderColor;
 pub type PowerPreference = wgpu::PowerPreference;
 
 /// Pod trait for custom uniform buffer structure
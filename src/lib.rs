@@ -4,11 +4,17 @@
  * The license is same as the one on the root.
 */
 
+pub mod atlas;
+pub mod cubemap;
 pub(crate) mod definition;
 pub mod header;
+pub mod material;
 pub mod objects;
+pub mod post_processing;
 pub mod primitive_shapes;
 pub mod render;
+pub mod render_graph;
+pub mod shadows;
 pub mod utils;
 pub mod window;
 pub use crate::header::*;
@@ -0,0 +1,222 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::header::{
+    Material, MaterialHandle, MaterialSettings, Mipmaps, Renderer, SamplerSettings, TextureData,
+    TextureMode,
+};
+use wgpu::util::DeviceExt;
+
+/// The uniforms of `MATERIAL_SHADER`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct MaterialUniforms {
+    base_color: [f32; 4],
+    emissive: [f32; 4],
+    factors: [f32; 4],
+}
+
+impl Default for MaterialSettings {
+    /// The defaults of glTF, a white rough metal with repeating textures
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            sampler: SamplerSettings::with_mode(TextureMode::Repeat),
+        }
+    }
+}
+
+impl Renderer {
+    pub(crate) fn build_material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        // the base color and sampler are at the bindings of the texture layout,
+        // so the shaders sample it the same way
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material_bind_group_layout"),
+            entries: &[
+                texture(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture(2),
+                texture(3),
+                texture(4),
+                texture(5),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Creates a physically based material, to be given to objects with `Object::set_material`
+    pub fn build_material(
+        &mut self,
+        name: &'static str,
+        settings: MaterialSettings,
+    ) -> anyhow::Result<MaterialHandle> {
+        let bind_group = self.build_material_bind_group(name, settings)?;
+        self.materials.push(Material { name, bind_group });
+
+        Ok(MaterialHandle(self.materials.len() - 1))
+    }
+
+    /// Replaces the maps and factors of the material, for every object using it
+    pub fn update_material(
+        &mut self,
+        material: MaterialHandle,
+        settings: MaterialSettings,
+    ) -> anyhow::Result<()> {
+        let name = self
+            .materials
+            .get(material.0)
+            .ok_or_else(|| anyhow::anyhow!("There is no material {:?}", material))?
+            .name;
+        self.materials[material.0].bind_group = self.build_material_bind_group(name, settings)?;
+
+        Ok(())
+    }
+
+    fn build_material_bind_group(
+        &self,
+        name: &'static str,
+        settings: MaterialSettings,
+    ) -> anyhow::Result<wgpu::BindGroup> {
+        let white = [255, 255, 255, 255];
+        let map = |texture: Option<TextureData>, fallback: [u8; 4], format| {
+            let img = match texture {
+                Some(texture) => self.load_image(texture).map_err(|error| {
+                    anyhow::Error::new(error)
+                        .context(format!("Couldn't load a map of the material {}", name))
+                })?,
+                None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    1,
+                    1,
+                    image::Rgba(fallback),
+                )),
+            };
+            let mip_level_count = Mipmaps::Full.level_count(img.width(), img.height());
+            anyhow::Ok(self.build_texture_view(name, &img, format, mip_level_count))
+        };
+        let base_color = map(
+            settings.base_color_texture,
+            white,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )?;
+        let metallic_roughness = map(
+            settings.metallic_roughness_texture,
+            white,
+            wgpu::TextureFormat::Rgba8Unorm,
+        )?;
+        let normal = map(
+            settings.normal_texture,
+            [128, 128, 255, 255],
+            wgpu::TextureFormat::Rgba8Unorm,
+        )?;
+        let occlusion = map(
+            settings.occlusion_texture,
+            white,
+            wgpu::TextureFormat::Rgba8Unorm,
+        )?;
+        let emissive = map(
+            settings.emissive_texture,
+            white,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )?;
+
+        let sampler = self.build_sampler(name, &settings.sampler)?;
+        let uniforms = MaterialUniforms {
+            base_color: settings.base_color,
+            emissive: [
+                settings.emissive[0],
+                settings.emissive[1],
+                settings.emissive[2],
+                settings.normal_scale,
+            ],
+            factors: [
+                settings.metallic,
+                settings.roughness,
+                settings.occlusion_strength,
+                0.0,
+            ],
+        };
+        let uniform_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Material Uniform"),
+                contents: bytemuck::cast_slice(&[uniforms]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let view = wgpu::BindingResource::TextureView;
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(name),
+            layout: &self.material_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: view(&base_color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view(&metallic_roughness),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: view(&normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: view(&occlusion),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: view(&emissive),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(bind_group)
+    }
+}
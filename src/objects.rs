@@ -5,50 +5,105 @@
 */
 
 use crate::header::{
-    normalize, uniform_type, Engine, Object, ObjectSettings, Pipeline, Renderer, RotateAxis,
-    TextureData, Textures, Vertex,
+    normalize, uniform_type, BlendMode, Engine, Indices, Instance, MaterialHandle, Object,
+    ObjectChanges, ObjectSettings, Pipeline, Renderer, RotateAxis, TextureAtlas, TextureData,
+    Textures, Vertex, VertexLayout, VertexLayoutDescriptor,
 };
 use crate::uniform_type::{Array4, Matrix};
-use crate::utils::default_resources::{DEFAULT_MATRIX_4, DEFAULT_SHADER, DEFAULT_TEXTURE};
+use crate::utils::default_resources::{DEFAULT_MATRIX_4, DEFAULT_TEXTURE};
 
 impl Renderer {
     pub fn build_object(
         &mut self,
         name: &'static str,
         verticies: Vec<Vertex>,
-        indicies: Vec<u16>,
+        indicies: impl Into<Indices>,
         settings: ObjectSettings,
     ) -> anyhow::Result<Object> {
-        let vertex_buffer = self.build_vertex_buffer(verticies.clone(), indicies.clone())?;
+        let mut object = self.build_object_from_bytes(
+            name,
+            bytemuck::cast_slice(verticies.as_slice()),
+            Vertex::layout(),
+            indicies.into(),
+            settings,
+        )?;
+        object.vertices = verticies;
+
+        Ok(object)
+    }
+
+    /// Creates an object with vertices of your own type, e.g. with vertex colors or tangents
+    pub fn build_object_with_layout<V: VertexLayout>(
+        &mut self,
+        name: &'static str,
+        verticies: Vec<V>,
+        indicies: impl Into<Indices>,
+        settings: ObjectSettings,
+    ) -> anyhow::Result<Object> {
+        let verticies: &[u8] = bytemuck::cast_slice(verticies.as_slice());
+        let mut object =
+            self.build_object_from_bytes(name, verticies, V::layout(), indicies.into(), settings)?;
+        object.custom_vertices = Some(verticies.to_vec());
+
+        Ok(object)
+    }
+
+    fn build_object_from_bytes(
+        &mut self,
+        name: &'static str,
+        verticies: &[u8],
+        vertex_layout: VertexLayoutDescriptor,
+        indicies: Indices,
+        settings: ObjectSettings,
+    ) -> anyhow::Result<Object> {
+        self.object_count += 1;
+        let vertex_buffer = self.build_vertex_buffer_from_bytes(verticies, indicies.clone())?;
 
-        let uniform = self.build_uniform_buffer(&vec![
+        let uniform_buffers = vec![
             self.build_uniform_buffer_part("Transformation Matrix", DEFAULT_MATRIX_4),
             self.build_uniform_buffer_part("Color", settings.color),
-        ])?;
+        ];
+        let uniform = self.build_uniform_buffer(&uniform_buffers)?;
 
-        let shader = self.build_shader(
+        let shader_builder = ShaderBuilder::new_with_layout(settings.camera_effect, &vertex_layout);
+        let shader = self.build_cached_shader(
             name,
-            DEFAULT_SHADER.to_string(),
+            shader_builder.build_shader(),
             Some(&uniform.1),
-            settings.shader_settings,
+            uniform_buffers.len(),
+            &vertex_layout,
+            settings
+                .shader_settings
+                .resolve_depth_compare(settings.camera_effect),
         )?;
 
-        let texture = self.build_texture(
-            "Default Texture",
-            TextureData::Bytes(DEFAULT_TEXTURE.to_vec()),
-            crate::header::TextureMode::Clamp,
-            //crate::header::TextureFormat::PNG
-        )?;
+        // the default texture is decoded once by the renderer, and shared by the objects
+        let texture = match self.default_data.as_ref() {
+            Some(default_data) => default_data.0.clone(),
+            None => self.build_texture(
+                "Default Texture",
+                TextureData::Bytes(DEFAULT_TEXTURE.to_vec()),
+                crate::header::TextureMode::Clamp,
+                //crate::header::TextureFormat::PNG
+            )?,
+        };
+
+        let instances = vec![Instance::default()];
+        let instance_buffer = self.build_instance_buffer(&instances)?;
 
         Ok(Object {
-            name: name,
-            vertices: verticies,
+            name,
+            vertices: Vec::new(),
             indices: indicies,
+            instances,
+            vertex_layout,
+            custom_vertices: None,
             pipeline: Pipeline {
                 vertex_buffer,
-                shader: shader,
-                texture: texture,
+                shader,
+                texture,
                 uniform: Some(uniform.0),
+                instance_buffer,
             },
             uniform_layout: uniform.1,
             size: settings.size,
@@ -58,20 +113,24 @@ impl Renderer {
                 settings.position.1,
                 settings.position.2,
             ),
-            changed: false,
+            changed: ObjectChanges::default(),
             transformation_matrix: DEFAULT_MATRIX_4.to_im(),
             inverse_transformation_matrix: Matrix::from_im(nalgebra_glm::transpose(
                 &nalgebra_glm::inverse(&DEFAULT_MATRIX_4.to_im()),
             )),
             uniform_color: settings.color,
             color: settings.color,
-            shader_builder: ShaderBuilder::new(settings.camera_effect),
+            shader_builder,
             shader_settings: settings.shader_settings,
+            layer: settings.layer,
+            z_index: settings.z_index,
+            creation_order: self.object_count,
             camera_effect: settings.camera_effect,
-            uniform_buffers: vec![
-                self.build_uniform_buffer_part("Transformation Matrix", DEFAULT_MATRIX_4),
-                self.build_uniform_buffer_part("Color", settings.color),
-            ],
+            cast_shadows: settings.cast_shadows,
+            receive_shadows: settings.receive_shadows,
+            material: None,
+            uv_region: None,
+            uniform_buffers,
         })
     }
 }
@@ -82,15 +141,37 @@ impl Engine {
         &mut self,
         name: &'static str,
         verticies: Vec<Vertex>,
-        indicies: Vec<u16>,
+        indicies: impl Into<Indices>,
         settings: ObjectSettings,
     ) -> anyhow::Result<()> {
-        Self::add_object(
-            &mut self.objects,
-            name,
-            self.renderer
-                .build_object(name, verticies, indicies, settings)?,
-        )?;
+        let object = self
+            .renderer
+            .build_object(name, verticies, indicies, settings)?;
+        self.insert_new_object(name, object, settings)
+    }
+
+    /// Creates a new object with vertices of your own type, e.g. with vertex colors or tangents.
+    /// The type describes its fields through the `VertexLayout` trait
+    pub fn new_object_with_layout<V: VertexLayout>(
+        &mut self,
+        name: &'static str,
+        verticies: Vec<V>,
+        indicies: impl Into<Indices>,
+        settings: ObjectSettings,
+    ) -> anyhow::Result<()> {
+        let object = self
+            .renderer
+            .build_object_with_layout(name, verticies, indicies, settings)?;
+        self.insert_new_object(name, object, settings)
+    }
+
+    fn insert_new_object(
+        &mut self,
+        name: &'static str,
+        object: Object,
+        settings: ObjectSettings,
+    ) -> anyhow::Result<()> {
+        Self::add_object(&mut self.objects, name, object)?;
 
         Self::update_object(&mut self.objects, name, |object| {
             object.scale(settings.scale.0, settings.scale.1, settings.scale.2);
@@ -106,6 +187,24 @@ impl Engine {
         Ok(())
     }
 
+    /// Creates a new object that is drawn once for each of the instances, in a single draw call.
+    /// The instances are placed relative to the object, so moving the object moves all of them
+    pub fn new_instanced_object(
+        &mut self,
+        name: &'static str,
+        verticies: Vec<Vertex>,
+        indicies: impl Into<Indices>,
+        instances: Vec<Instance>,
+        settings: ObjectSettings,
+    ) -> anyhow::Result<()> {
+        self.new_object(name, verticies, indicies, settings)?;
+        Self::update_object(&mut self.objects, name, |object| {
+            object.set_instances(instances.clone());
+        });
+
+        Ok(())
+    }
+
     pub fn add_object(
         objects: &mut std::collections::HashMap<&'static str, Object>,
         key: &'static str,
@@ -147,6 +246,8 @@ impl Object {
         self.inverse_transformation_matrix = Matrix::from_im(nalgebra_glm::transpose(
             &nalgebra_glm::inverse(&self.transformation_matrix),
         ));
+
+        self.changed.transform = true;
     }
     /// Resizes an object in pixels which are relative to the window
     pub fn resize(
@@ -211,7 +312,7 @@ impl Object {
             &nalgebra_glm::inverse(&self.transformation_matrix),
         ));
 
-        self.changed = true;
+        self.changed.transform = true;
     }
 
     /// Moves the object by the amount you specify in the axis you specify
@@ -223,7 +324,7 @@ impl Object {
             &nalgebra_glm::inverse(&self.transformation_matrix),
         ));
 
-        self.changed = true;
+        self.changed.transform = true;
     }
 
     /// Sets the position of the object in 3D space relative to the window
@@ -275,12 +376,17 @@ impl Object {
         self.position.2 = z;
     }
 
-    /// Changes the color of the object. If textures exist, the color of textures will change
+    /// Changes the color of the object, which the texture is multiplied with. On objects lit by
+    /// a `LightManager`, the ambient color is multiplied on top of it.
+    ///
+    /// The alpha is only visible if the object uses a blend mode other than `BlendMode::Opaque`
     pub fn set_color(&mut self, red: f32, green: f32, blue: f32, alpha: f32) -> anyhow::Result<()> {
         self.color = Array4 {
             data: [red, green, blue, alpha],
         };
-        self.changed = true;
+        // lit objects get theirs from the light manager on its next update
+        self.uniform_color = self.color;
+        self.changed.color = true;
         Ok(())
     }
 
@@ -295,55 +401,200 @@ impl Object {
         self.uniform_color = Array4 {
             data: [red, green, blue, alpha],
         };
-        self.changed = true;
+        self.changed.color = true;
 
         Ok(())
     }
 
+    /// Replaces the instances of the object. Each instance is a copy of the object drawn
+    /// with its own transformation and color
+    pub fn set_instances(&mut self, instances: Vec<Instance>) {
+        self.instances = instances;
+        self.changed.instances = true;
+    }
+
+    /// Adds another copy of the object
+    pub fn add_instance(&mut self, instance: Instance) {
+        self.instances.push(instance);
+        self.changed.instances = true;
+    }
+
+    /// Changes how the object is blended with what is behind it
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.shader_settings.blend_mode = blend_mode;
+        self.changed.shader = true;
+    }
+
+    /// Moves the object to another render layer. Higher layers are drawn on top of the lower ones
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    /// Changes the draw order of the object within its layer
+    pub fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+    }
+
+    /// Returns true if the object is drawn in the transparent pass
+    pub fn is_transparent(&self) -> bool {
+        self.shader_settings.blend_mode.is_transparent()
+    }
+
     /// Replaces the object's texture with provided one
     pub fn set_texture(&mut self, texture: Textures) -> anyhow::Result<()> {
         self.pipeline.texture = texture;
-        self.changed = true;
 
         Ok(())
     }
 
-    /// Update and apply changes done to an object
+    /// Gives the object a physically based material, which takes effect once
+    /// it's lit by a `LightManager`
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.material = Some(material);
+    }
+
+    /// Shows a region of the atlas on the object, by using the atlas as its texture
+    /// and remapping the uvs of its vertices to the region
+    pub fn set_sprite(&mut self, atlas: &TextureAtlas, region: &str) -> anyhow::Result<()> {
+        let uv_region = atlas
+            .region(region)
+            .ok_or_else(|| anyhow::anyhow!("There is no region {} in the atlas", region))?;
+        if self.custom_vertices.is_some() {
+            anyhow::bail!("Sprites need objects with the default vertex layout");
+        }
+
+        self.pipeline.texture = atlas.texture.clone();
+        if self.uv_region != Some(uv_region) {
+            self.uv_region = Some(uv_region);
+            self.changed.vertices = true;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the mesh of the object
+    pub fn set_vertices(&mut self, verticies: Vec<Vertex>, indicies: impl Into<Indices>) {
+        self.vertices = verticies;
+        self.custom_vertices = None;
+        self.indices = indicies.into();
+        self.set_vertex_layout(Vertex::layout());
+        self.changed.vertices = true;
+    }
+
+    /// Replaces the mesh of the object with vertices of your own type
+    pub fn set_vertices_with_layout<V: VertexLayout>(
+        &mut self,
+        verticies: Vec<V>,
+        indicies: impl Into<Indices>,
+    ) {
+        self.vertices = Vec::new();
+        self.custom_vertices = Some(bytemuck::cast_slice(verticies.as_slice()).to_vec());
+        self.indices = indicies.into();
+        self.set_vertex_layout(V::layout());
+        self.changed.vertices = true;
+    }
+
+    // a different layout needs a shader with the matching vertex input
+    fn set_vertex_layout(&mut self, vertex_layout: VertexLayoutDescriptor) {
+        if self.vertex_layout != vertex_layout {
+            self.shader_builder =
+                ShaderBuilder::new_with_layout(self.camera_effect, &vertex_layout);
+            self.vertex_layout = vertex_layout;
+            self.changed.shader = true;
+        }
+    }
+
+    /// Update and apply all the data of the object, whether it has changed or not
     pub fn update(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
-        self.update_vertex_buffer(renderer)?;
-        self.update_uniform_buffer(renderer)?;
-        self.update_shader(renderer)?;
-        self.changed = false;
+        self.changed = ObjectChanges::all();
+        self.update_changes(renderer)
+    }
+
+    /// Applies only the parts of the object that have changed since the last update.
+    /// This is called by the engine for every object before each frame
+    pub fn update_changes(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
+        if self.changed.vertices {
+            self.update_vertex_buffer(renderer)?;
+        }
+        if self.changed.instances {
+            self.update_instance_buffer(renderer)?;
+        }
+        if self.changed.transform {
+            self.update_transform_buffer(renderer);
+        }
+        if self.changed.color {
+            self.update_color_buffer(renderer);
+        }
+        if self.changed.shader {
+            self.update_shader(renderer)?;
+        }
+        self.changed = ObjectChanges::default();
+
         Ok(())
     }
 
     pub(crate) fn update_vertex_buffer(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
-        let updated_buffer =
-            renderer.build_vertex_buffer(self.vertices.clone(), self.indices.clone())?;
-        self.pipeline.vertex_buffer = updated_buffer;
+        // the vertices keep their own uvs, only the buffer has the ones of the atlas region
+        let sprite_verticies: Vec<Vertex>;
+        let verticies = match (&self.custom_vertices, self.uv_region) {
+            (Some(verticies), _) => verticies.as_slice(),
+            (None, Some(uv_region)) => {
+                sprite_verticies = self
+                    .vertices
+                    .iter()
+                    .map(|vertex| Vertex {
+                        uv: uv_region.uv(vertex.uv),
+                        ..*vertex
+                    })
+                    .collect();
+                bytemuck::cast_slice(sprite_verticies.as_slice())
+            }
+            (None, None) => bytemuck::cast_slice(self.vertices.as_slice()),
+        };
+        renderer.update_vertex_buffer_from_bytes(
+            &mut self.pipeline.vertex_buffer,
+            verticies,
+            &self.indices,
+        )
+    }
 
-        Ok(())
+    pub(crate) fn update_instance_buffer(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
+        renderer.update_instance_buffer(&mut self.pipeline.instance_buffer, &self.instances)
+    }
+
+    pub(crate) fn update_transform_buffer(&mut self, renderer: &mut Renderer) {
+        renderer.queue.write_buffer(
+            &self.uniform_buffers[0],
+            0,
+            bytemuck::cast_slice(&[uniform_type::Matrix::from_im(self.transformation_matrix)]),
+        );
+    }
+
+    pub(crate) fn update_color_buffer(&mut self, renderer: &mut Renderer) {
+        renderer.queue.write_buffer(
+            &self.uniform_buffers[1],
+            0,
+            bytemuck::cast_slice(&[self.uniform_color]),
+        );
     }
 
     pub(crate) fn update_shader(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
-        let updated_shader = renderer.build_shader(
+        let updated_shader = renderer.build_cached_shader(
             self.name,
             self.shader_builder.build_shader(),
             Some(&self.uniform_layout),
-            self.shader_settings,
+            self.uniform_buffers.len(),
+            &self.vertex_layout,
+            self.shader_settings
+                .resolve_depth_compare(self.camera_effect),
         )?;
         self.pipeline.shader = updated_shader;
 
         Ok(())
     }
 
+    /// Rebuilds the uniform bind group, needed when uniform buffers are added or replaced
     pub(crate) fn update_uniform_buffer(&mut self, renderer: &mut Renderer) -> anyhow::Result<()> {
-        self.uniform_buffers[0] = renderer.build_uniform_buffer_part(
-            "Transformation Matrix",
-            uniform_type::Matrix::from_im(self.transformation_matrix),
-        );
-        self.uniform_buffers[1] = renderer.build_uniform_buffer_part("Color", self.uniform_color);
-
         let updated_buffer = renderer.build_uniform_buffer(&self.uniform_buffers)?;
 
         self.pipeline.uniform = Some(updated_buffer.0);
@@ -363,6 +614,24 @@ pub struct ShaderBuilder {
 
 impl ShaderBuilder {
     pub fn new(camera_effect: bool) -> Self {
+        Self::new_with_layout(camera_effect, &Vertex::layout())
+    }
+
+    /// Creates a shader whose vertex input matches the given layout. A `position` attribute is
+    /// required, `texture_coordinates` and `color` are used when present
+    pub fn new_with_layout(camera_effect: bool, vertex_layout: &VertexLayoutDescriptor) -> Self {
+        let vertex_input = vertex_input(vertex_layout);
+        let instance_input = instance_input(vertex_layout.next_location());
+        let position = position_expression(vertex_layout);
+        let texture_coordinates = texture_coordinates_expression(vertex_layout);
+        let vertex_color = match vertex_layout.format_of("color").map(component_count) {
+            Some(1) => "vec4<f32>(vec3<f32>(f32(input.color)), 1.0)",
+            Some(2) => "vec4<f32>(vec2<f32>(input.color), 0.0, 1.0)",
+            Some(3) => "vec4<f32>(vec3<f32>(input.color), 1.0)",
+            Some(_) => "vec4<f32>(input.color)",
+            None => "vec4<f32>(1.0, 1.0, 1.0, 1.0)",
+        };
+
         Self {
             blocks: format!(
                 // step 1 define blocks
@@ -392,15 +661,15 @@ var<uniform> camera_uniform: CameraUniforms;"#
             ),
             input_and_output: format!(
                 // step 2 define input and output for vertex
-                "\n{}",
-                r#"struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) texture_coordinates: vec2<f32>,
-};
-
+                "\nstruct VertexInput {{\n{}\n}};\n\nstruct InstanceInput {{\n{}\n}};\n{}",
+                vertex_input,
+                instance_input,
+                r#"
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) texture_coordinates: vec2<f32>,
+    @location(1) instance_color: vec4<f32>,
+    @location(2) vertex_color: vec4<f32>,
 };"#
             ),
             texture_data: format!(
@@ -414,15 +683,24 @@ var sampler_diffuse: sampler;"#
             ),
             vertex_stage: format!(
                 // step 4 vertex stage according to data before
-                "\n// ===== VERTEX STAGE ===== //\n{}\n{}\n{}",
+                "\n// ===== VERTEX STAGE ===== //\n{}\nout.texture_coordinates = {};\nout.vertex_color = {};\n{}\n{}",
                 r#"@vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
+fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let instance_matrix = mat4x4<f32>(
+        instance.transform_matrix_0,
+        instance.transform_matrix_1,
+        instance.transform_matrix_2,
+        instance.transform_matrix_3,
+    );
+
     var out: VertexOutput;
-    out.texture_coordinates = input.texture_coordinates;"#,
+    out.instance_color = instance.color;"#,
+                texture_coordinates,
+                vertex_color,
                 if camera_effect {
-                    "out.position = camera_uniform.camera_matrix * (transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0));"
+                    format!("out.position = camera_uniform.camera_matrix * (transform_uniform.transform_matrix * (instance_matrix * {}));", position)
                 } else {
-                    "out.position = transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0);"
+                    format!("out.position = transform_uniform.transform_matrix * (instance_matrix * {});", position)
                 },
                 r#"return out;
 }"#
@@ -432,7 +710,7 @@ fn vs_main(input: VertexInput) -> VertexOutput {
                 "\n// ===== Fragment STAGE ===== //\n{}",
                 r#"@fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * fragment_uniforms.color;
+    return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * fragment_uniforms.color * input.instance_color * input.vertex_color;
 }"#
             ),
         }
@@ -449,3 +727,117 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
         )
     }
 }
+
+/// The fields of the `VertexInput` struct, one for each attribute of the layout
+pub(crate) fn vertex_input(vertex_layout: &VertexLayoutDescriptor) -> String {
+    vertex_layout
+        .names
+        .iter()
+        .zip(vertex_layout.attributes.iter())
+        .map(|(name, attribute)| {
+            format!(
+                "    @location({}) {}: {},",
+                attribute.shader_location,
+                name,
+                wgsl_type(attribute.format)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The fields of the `InstanceInput` struct, starting at the given shader location
+pub(crate) fn instance_input(location: u32) -> String {
+    [
+        "transform_matrix_0",
+        "transform_matrix_1",
+        "transform_matrix_2",
+        "transform_matrix_3",
+        "color",
+    ]
+    .iter()
+    .enumerate()
+    .map(|(i, name)| {
+        format!(
+            "    @location({}) {}: vec4<f32>,",
+            location + i as u32,
+            name
+        )
+    })
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+/// The `position` attribute of the vertex input as a `vec4<f32>`
+pub(crate) fn position_expression(vertex_layout: &VertexLayoutDescriptor) -> &'static str {
+    match vertex_layout.format_of("position").map(component_count) {
+        Some(2) => "vec4<f32>(input.position, 0.0, 1.0)",
+        Some(4) => "vec4<f32>(input.position)",
+        _ => "vec4<f32>(input.position, 1.0)",
+    }
+}
+
+/// The texture coordinates of the vertex as a `vec2<f32>`, zero when the layout has none
+pub(crate) fn texture_coordinates_expression(
+    vertex_layout: &VertexLayoutDescriptor,
+) -> &'static str {
+    match vertex_layout.format_of("texture_coordinates") {
+        Some(_) => "vec2<f32>(input.texture_coordinates)",
+        None => "vec2<f32>(0.0, 0.0)",
+    }
+}
+
+/// The WGSL type a vertex attribute is read as in the shader
+pub(crate) fn wgsl_type(format: wgpu::VertexFormat) -> String {
+    let scalar = match format {
+        wgpu::VertexFormat::Uint8x2
+        | wgpu::VertexFormat::Uint8x4
+        | wgpu::VertexFormat::Uint16x2
+        | wgpu::VertexFormat::Uint16x4
+        | wgpu::VertexFormat::Uint32
+        | wgpu::VertexFormat::Uint32x2
+        | wgpu::VertexFormat::Uint32x3
+        | wgpu::VertexFormat::Uint32x4 => "u32",
+        wgpu::VertexFormat::Sint8x2
+        | wgpu::VertexFormat::Sint8x4
+        | wgpu::VertexFormat::Sint16x2
+        | wgpu::VertexFormat::Sint16x4
+        | wgpu::VertexFormat::Sint32
+        | wgpu::VertexFormat::Sint32x2
+        | wgpu::VertexFormat::Sint32x3
+        | wgpu::VertexFormat::Sint32x4 => "i32",
+        _ => "f32",
+    };
+
+    match component_count(format) {
+        1 => scalar.to_string(),
+        count => format!("vec{}<{}>", count, scalar),
+    }
+}
+
+fn component_count(format: wgpu::VertexFormat) -> u32 {
+    match format {
+        wgpu::VertexFormat::Float32
+        | wgpu::VertexFormat::Float64
+        | wgpu::VertexFormat::Uint32
+        | wgpu::VertexFormat::Sint32 => 1,
+        wgpu::VertexFormat::Float32x3
+        | wgpu::VertexFormat::Float64x3
+        | wgpu::VertexFormat::Uint32x3
+        | wgpu::VertexFormat::Sint32x3 => 3,
+        wgpu::VertexFormat::Uint8x4
+        | wgpu::VertexFormat::Sint8x4
+        | wgpu::VertexFormat::Unorm8x4
+        | wgpu::VertexFormat::Snorm8x4
+        | wgpu::VertexFormat::Uint16x4
+        | wgpu::VertexFormat::Sint16x4
+        | wgpu::VertexFormat::Unorm16x4
+        | wgpu::VertexFormat::Snorm16x4
+        | wgpu::VertexFormat::Float16x4
+        | wgpu::VertexFormat::Float32x4
+        | wgpu::VertexFormat::Float64x4
+        | wgpu::VertexFormat::Uint32x4
+        | wgpu::VertexFormat::Sint32x4 => 4,
+        _ => 2,
+    }
+}
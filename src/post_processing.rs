@@ -0,0 +1,442 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::{
+    header::{
+        PostEffect, PostEffectData, RenderGraph, RenderGraphContext, RenderGraphName,
+        RenderGraphPass, RenderGraphTexture, RenderGraphTextureSize, Renderer, Shaders,
+        TonemapOperator,
+    },
+    utils::default_resources::{
+        BLOOM_BLUR_SHADER, BLOOM_BLUR_X_SHADER, BLOOM_BLUR_Y_SHADER, BLOOM_SHADER,
+        COLOR_GRADING_SHADER, FXAA_SHADER, POST_EFFECT_SHADER, TONEMAPPING_SHADER, VIGNETTE_SHADER,
+    },
+};
+use wgpu::util::DeviceExt;
+
+/// The name of the pass of the effect at the index, which is also the name of the texture it writes to
+fn post_effect_pass(index: usize) -> RenderGraphName {
+    format!("post effect {}", index).into()
+}
+
+/// The name of a pass drawn before the effect at the index, which is also the name of its texture
+fn post_effect_stage(index: usize, stage: usize) -> RenderGraphName {
+    format!("post effect {} stage {}", index, stage).into()
+}
+
+impl PostEffect {
+    /// Bloom with a soft glow around colors brighter than 80%
+    pub fn bloom() -> Self {
+        Self::Bloom {
+            threshold: 0.8,
+            intensity: 1.0,
+            radius: 16.0,
+        }
+    }
+
+    /// ACES tonemapping without changing the exposure
+    pub fn tonemapping() -> Self {
+        Self::Tonemapping {
+            operator: TonemapOperator::Aces,
+            exposure: 1.0,
+        }
+    }
+
+    /// FXAA with the commonly used thresholds
+    pub fn fxaa() -> Self {
+        Self::Fxaa {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+        }
+    }
+
+    /// A vignette that starts halfway to the corners
+    pub fn vignette() -> Self {
+        Self::Vignette {
+            intensity: 0.5,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+
+    /// Color grading that keeps the colors as they are, to be adjusted from there
+    pub fn color_grading() -> Self {
+        Self::ColorGrading {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            tint: [1.0, 1.0, 1.0],
+        }
+    }
+
+    fn shader(&self) -> &'static str {
+        match self {
+            Self::Bloom { .. } => BLOOM_SHADER,
+            Self::Tonemapping { .. } => TONEMAPPING_SHADER,
+            Self::Fxaa { .. } => FXAA_SHADER,
+            Self::Vignette { .. } => VIGNETTE_SHADER,
+            Self::ColorGrading { .. } => COLOR_GRADING_SHADER,
+        }
+    }
+
+    /// The shaders of the passes drawn before the effect, e.g. the blur of the bloom
+    fn stage_shaders(&self) -> Vec<String> {
+        match self {
+            Self::Bloom { .. } => vec![
+                format!("{}{}", BLOOM_BLUR_X_SHADER, BLOOM_BLUR_SHADER),
+                format!("{}{}", BLOOM_BLUR_Y_SHADER, BLOOM_BLUR_SHADER),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The parameters as they are laid out in the uniform buffer of the effect
+    fn uniform_data(&self) -> [[f32; 4]; 2] {
+        match *self {
+            Self::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => [[threshold, intensity, radius, 0.0], [0.0; 4]],
+            Self::Tonemapping { operator, exposure } => {
+                let operator = match operator {
+                    TonemapOperator::Reinhard => 0.0,
+                    TonemapOperator::Aces => 1.0,
+                };
+                [[operator, exposure, 0.0, 0.0], [0.0; 4]]
+            }
+            Self::Fxaa {
+                edge_threshold,
+                edge_threshold_min,
+            } => [[edge_threshold, edge_threshold_min, 0.0, 0.0], [0.0; 4]],
+            Self::Vignette {
+                intensity,
+                radius,
+                smoothness,
+            } => [[intensity, radius, smoothness, 0.0], [0.0; 4]],
+            Self::ColorGrading {
+                brightness,
+                contrast,
+                saturation,
+                tint,
+            } => [
+                [brightness, contrast, saturation, 0.0],
+                [tint[0], tint[1], tint[2], 1.0],
+            ],
+        }
+    }
+}
+
+impl Renderer {
+    /// Brings the post effect passes of the render graph in line with `post_processing`,
+    /// and uploads the current parameters of the effects
+    pub(crate) fn update_post_processing(&mut self) -> anyhow::Result<()> {
+        let chain = self.post_effect_chain();
+        let up_to_date = chain.len() == self.post_processing_data.len()
+            && chain.iter().zip(self.post_processing_data.iter()).all(
+                |((effect, format), data)| {
+                    std::mem::discriminant(effect) == data.effect && *format == data.format
+                },
+            );
+        if !up_to_date {
+            self.rebuild_post_processing()?;
+        }
+
+        for ((effect, _), data) in chain.iter().zip(self.post_processing_data.iter()) {
+            self.queue.write_buffer(
+                &data.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&effect.uniform_data()),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds the effects and reconnects their passes, e.g. after the format of the scene changed
+    pub(crate) fn rebuild_post_processing(&mut self) -> anyhow::Result<()> {
+        let chain = self.post_effect_chain();
+        self.post_processing_data = chain
+            .iter()
+            .map(|(effect, format)| self.build_post_effect(effect, *format))
+            .collect();
+
+        self.connect_post_processing(&chain)
+    }
+
+    /// The effects that are applied, along with the format each of them draws in. With HDR on,
+    /// tonemapping is added at the end unless the effects already include it, and the effects
+    /// before the tonemapping draw in the HDR format
+    fn post_effect_chain(&self) -> Vec<(PostEffect, wgpu::TextureFormat)> {
+        let mut effects = self.post_processing.clone();
+        if let Some(hdr) = self.hdr {
+            if !effects
+                .iter()
+                .any(|i| matches!(i, PostEffect::Tonemapping { .. }))
+            {
+                effects.push(PostEffect::Tonemapping {
+                    operator: hdr.operator,
+                    exposure: hdr.exposure,
+                });
+            }
+        }
+
+        let mut format = self.render_format();
+        effects
+            .into_iter()
+            .map(|effect| {
+                if matches!(effect, PostEffect::Tonemapping { .. }) {
+                    format = self.config.format;
+                }
+                (effect, format)
+            })
+            .collect()
+    }
+
+    fn build_post_effect(
+        &self,
+        effect: &PostEffect,
+        format: wgpu::TextureFormat,
+    ) -> PostEffectData {
+        let stages: Vec<Shaders> = effect
+            .stage_shaders()
+            .iter()
+            .map(|shader| {
+                self.build_fullscreen_pipeline(
+                    "Post Effect Stage",
+                    format!("{}{}", POST_EFFECT_SHADER, shader).as_str(),
+                    &[
+                        &self.texture_bind_group_layout,
+                        &self.default_uniform_bind_group_layout,
+                    ],
+                    format,
+                    None,
+                    1,
+                )
+            })
+            .collect();
+
+        // the last stage is read at group 2
+        let mut layouts = vec![
+            &self.texture_bind_group_layout,
+            &self.default_uniform_bind_group_layout,
+        ];
+        if !stages.is_empty() {
+            layouts.push(&self.texture_bind_group_layout);
+        }
+        let pipeline = self.build_fullscreen_pipeline(
+            "Post Effect",
+            format!("{}{}", POST_EFFECT_SHADER, effect.shader()).as_str(),
+            &layouts,
+            format,
+            None,
+            1,
+        );
+
+        let uniform_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Post Effect Uniform"),
+                contents: bytemuck::cast_slice(&effect.uniform_data()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let uniform = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Effect Bind Group"),
+            layout: &self.default_uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        PostEffectData {
+            effect: std::mem::discriminant(effect),
+            format,
+            pipeline,
+            stages,
+            uniform_buffer,
+            uniform,
+        }
+    }
+
+    /// Redirects the scene into a texture when there are effects, and chains a pass for
+    /// each effect from there to the frame. The passes come right after the scene, so passes
+    /// added later to draw on the frame are drawn over the effects.
+    fn connect_post_processing(
+        &mut self,
+        chain: &[(PostEffect, wgpu::TextureFormat)],
+    ) -> anyhow::Result<()> {
+        let render_format = self.render_format();
+        let graph = &mut self.render_graph;
+        // the passes of the previous effects and their stages are numbered from 0 without gaps
+        let mut index = 0;
+        while graph.remove_pass(&post_effect_pass(index)).is_some() {
+            let mut stage = 0;
+            while graph
+                .remove_pass(&post_effect_stage(index, stage))
+                .is_some()
+            {
+                stage += 1;
+            }
+            index += 1;
+        }
+
+        let effects = chain.len();
+        let (from, to) = if effects == 0 {
+            (RenderGraph::SCENE_COLOR, RenderGraph::FRAME)
+        } else {
+            (RenderGraph::FRAME, RenderGraph::SCENE_COLOR)
+        };
+        if let Some(scene) = graph.pass_mut(RenderGraph::SCENE) {
+            for output in scene.color_outputs.iter_mut().filter(|i| **i == from) {
+                *output = to.into();
+            }
+        }
+        if effects == 0 {
+            return Ok(());
+        }
+
+        // the objects are drawn into it, so it's multisampled along with the frame
+        graph.set_texture(
+            RenderGraph::SCENE_COLOR,
+            RenderGraphTexture {
+                format: Some(render_format),
+                multisampled: true,
+                ..Default::default()
+            },
+        )?;
+
+        let mut position = graph
+            .passes
+            .iter()
+            .position(|i| i.name == RenderGraph::SCENE)
+            .map_or(graph.passes.len(), |i| i + 1);
+        for (index, (_, format)) in chain.iter().enumerate() {
+            let input: RenderGraphName = if index == 0 {
+                RenderGraph::SCENE_COLOR.into()
+            } else {
+                post_effect_pass(index - 1)
+            };
+            let output = if index + 1 == effects {
+                RenderGraph::FRAME.into()
+            } else {
+                graph.set_texture(
+                    post_effect_pass(index),
+                    RenderGraphTexture {
+                        format: Some(*format),
+                        ..Default::default()
+                    },
+                )?;
+                post_effect_pass(index)
+            };
+
+            // each stage reads the one before it, starting from the input of the effect
+            let mut previous = input.clone();
+            for stage in 0..self.post_processing_data[index].stages.len() {
+                let stage_output = post_effect_stage(index, stage);
+                graph.set_texture(
+                    stage_output.clone(),
+                    RenderGraphTexture {
+                        format: Some(*format),
+                        size: RenderGraphTextureSize::Scaled(0.5),
+                        ..Default::default()
+                    },
+                )?;
+                let stage_input = vec![previous];
+                graph.passes.insert(
+                    position,
+                    RenderGraphPass {
+                        inputs: stage_input.clone(),
+                        color_outputs: vec![stage_output.clone()],
+                        ..RenderGraphPass::new(stage_output.clone(), move |context| {
+                            context.draw_post_effect(
+                                index,
+                                Some(stage),
+                                &stage_input,
+                                &stage_output,
+                            )
+                        })
+                    },
+                );
+                position += 1;
+                previous = post_effect_stage(index, stage);
+            }
+            let inputs = if previous == input {
+                vec![input]
+            } else {
+                vec![input, previous]
+            };
+
+            graph.passes.insert(
+                position,
+                RenderGraphPass {
+                    inputs: inputs.clone(),
+                    color_outputs: vec![output.clone()],
+                    ..RenderGraphPass::new(post_effect_pass(index), move |context| {
+                        context.draw_post_effect(index, None, &inputs, &output)
+                    })
+                },
+            );
+            position += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> RenderGraphContext<'a> {
+    /// Draws the effect at the index, or one of its stages. The first input is read at group 0,
+    /// and the second one, the last stage of the effect, at group 2
+    fn draw_post_effect(
+        &mut self,
+        index: usize,
+        stage: Option<usize>,
+        inputs: &[RenderGraphName],
+        output: &str,
+    ) -> anyhow::Result<()> {
+        let renderer = self.renderer;
+        let effect = renderer
+            .post_processing_data
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("There is no post effect at index {}", index))?;
+        let pipeline = match stage {
+            Some(stage) => effect.stages.get(stage).ok_or_else(|| {
+                anyhow::anyhow!("The post effect at index {} has no stage {}", index, stage)
+            })?,
+            None => &effect.pipeline,
+        };
+        let bind_groups = inputs
+            .iter()
+            .map(|input| self.input_bind_group(input))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // effects don't need multisampling, so they are drawn straight into the resolved texture
+        let (view, resolve_target) = self.color_attachment(output)?;
+        let view = resolve_target.unwrap_or(view);
+
+        let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Effect Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_groups[0], &[]);
+        render_pass.set_bind_group(1, &effect.uniform, &[]);
+        if let Some(glow) = bind_groups.get(1) {
+            render_pass.set_bind_group(2, glow, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
@@ -132,7 +132,7 @@ pub fn cube(name: &'static str, engine: &mut Engine) -> anyhow::Result<()> {
             },
         ],
         vec![
-            0, 1, 2, 2, 3, 0, // top
+            0u16, 1, 2, 2, 3, 0, // top
             4, 5, 6, 6, 7, 4, // bottom
             8, 9, 10, 10, 11, 8, // right
             12, 13, 14, 14, 15, 12, // left
@@ -31,7 +31,7 @@ pub fn triangle(
                 normal: [0f32, 0f32, 0f32],
             },
         ],
-        vec![0, 1, 2],
+        vec![0u16, 1, 2],
         settings,
     )?;
 
@@ -68,7 +68,7 @@ pub fn square(
                 normal: [0f32, 0f32, 0f32],
             },
         ],
-        vec![2, 1, 0, 2, 0, 3],
+        vec![2u16, 1, 0, 2, 0, 3],
         settings,
     )?;
 
@@ -5,7 +5,10 @@
 */
 
 use crate::{
-    header::{uniform_type, Camera, Object, Renderer, ShaderSettings, TextureData},
+    header::{
+        uniform_type, Background, Camera, HdrSettings, Object, PipelineCache, RenderGraph,
+        RenderTarget, Renderer, ShaderSettings, Shaders, TextureData,
+    },
     utils::default_resources::{DEFAULT_COLOR, DEFAULT_MATRIX_4, DEFAULT_SHADER, DEFAULT_TEXTURE},
 };
 use anyhow::Result;
@@ -23,47 +26,48 @@ fn get_render_features() -> Features {
 
 impl Renderer {
     pub(crate) async fn new(
-        window: &Window,
+        window: Option<&Window>,
+        size: winit::dpi::PhysicalSize<u32>,
         power_preference: crate::PowerPreference,
+        force_fallback_adapter: bool,
+        sample_count: u32,
     ) -> anyhow::Result<Self> {
-        let size = window.inner_size();
-
         // The instance is a handle to our GPU
         let instance = wgpu::Instance::new(wgpu::Backends::all());
 
+        // A surface is only created when there is a window to present to, otherwise
+        // the renderer draws into an offscreen target
         #[cfg(not(feature = "android"))]
-        let surface = Some(unsafe { instance.create_surface(window) });
+        let surface = window.map(|window| unsafe { instance.create_surface(window) });
         #[cfg(feature = "android")]
-        let surface = None;
+        let surface: Option<wgpu::Surface> = None;
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: power_preference,
-                #[cfg(not(feature = "android"))]
-                compatible_surface: Some(surface.as_ref().unwrap()),
-                #[cfg(feature = "android")]
-                compatible_surface: surface,
-                force_fallback_adapter: false,
+                power_preference,
+                compatible_surface: surface.as_ref(),
+                force_fallback_adapter,
             })
             .await
-            .unwrap();
+            .ok_or_else(|| anyhow::anyhow!("Couldn't find a suitable graphics adapter"))?;
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("Device"),
-                    features: get_render_features(),
+                    // clamping to a border color is used when the adapter has it
+                    features: get_render_features()
+                        | (adapter.features() & Features::ADDRESS_MODE_CLAMP_TO_BORDER),
                     limits: wgpu::Limits::default(),
                 },
                 None, // Trace path
             )
-            .await
-            .unwrap();
+            .await?;
 
-        #[cfg(not(feature = "android"))]
-        let tex_format = surface.as_ref().unwrap().get_supported_formats(&adapter)[0];
-        #[cfg(feature = "android")]
-        let tex_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let tex_format = match surface {
+            Some(ref surface) => surface.get_supported_formats(&adapter)[0],
+            None => Self::HEADLESS_FORMAT,
+        };
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -81,8 +85,20 @@ impl Renderer {
             #[cfg(not(feature = "android"))]
             present_mode: wgpu::PresentMode::Fifo,
         };
-        #[cfg(not(feature = "android"))]
-        surface.as_ref().unwrap().configure(&device, &config);
+        if let Some(ref surface) = surface {
+            surface.configure(&device, &config);
+        }
+
+        // Without a window, frames are rendered into a texture owned by the renderer
+        let headless_target = if window.is_none() {
+            Some(Renderer::build_color_target(
+                "Headless Target",
+                &device,
+                &config,
+            ))
+        } else {
+            None
+        };
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -123,16 +139,27 @@ impl Renderer {
                 }],
             });
 
-        let depth_buffer = Renderer::build_depth_buffer("Depth Buffer", &device, &config);
+        Renderer::validate_sample_count(&adapter, sample_count, config.format)?;
+        let depth_buffer =
+            Renderer::build_depth_buffer("Depth Buffer", &device, &config, sample_count);
+        let msaa_buffer =
+            Renderer::build_msaa_buffer("MSAA Buffer", &device, &config, sample_count);
+        let lighting_bind_group_layout = Renderer::build_lighting_bind_group_layout(&device);
+        let shadow_maps = Renderer::build_shadow_maps(
+            &device,
+            &lighting_bind_group_layout,
+            &default_uniform_bind_group_layout,
+        );
+        let material_bind_group_layout = Renderer::build_material_bind_group_layout(&device);
+        let cubemap_bind_group_layout = Renderer::build_cubemap_bind_group_layout(&device);
 
         let mut renderer = Self {
             #[cfg(feature = "android")]
             instance,
             adapter,
-            #[cfg(not(feature = "android"))]
             surface,
-            #[cfg(feature = "android")]
-            surface: None,
+            headless_target,
+            frame_capture_target: None,
             device,
             queue,
             config,
@@ -141,10 +168,25 @@ impl Renderer {
             texture_bind_group_layout,
             default_uniform_bind_group_layout,
             depth_buffer,
+            sample_count,
+            msaa_buffer,
 
             default_data: None,
+            background: Background::default(),
+            background_data: None,
+            object_count: 0,
+            pipeline_cache: PipelineCache::default(),
             camera: None,
-            custom_render_pass: None,
+            render_graph: RenderGraph::new(),
+            post_processing: Vec::new(),
+            post_processing_data: Vec::new(),
+            hdr: None,
+            lighting_bind_group_layout,
+            shadow_maps,
+            material_bind_group_layout,
+            materials: Vec::new(),
+            cubemap_bind_group_layout,
+            skybox_data: None,
         };
 
         let default_texture = renderer.build_texture(
@@ -154,7 +196,7 @@ impl Renderer {
             //crate::header::TextureFormat::PNG
         )?;
 
-        let default_uniform = renderer.build_uniform_buffer(&vec![
+        let default_uniform = renderer.build_uniform_buffer(&[
             renderer.build_uniform_buffer_part("Transformation Matrix", DEFAULT_MATRIX_4),
             renderer.build_uniform_buffer_part(
                 "Color",
@@ -172,6 +214,8 @@ impl Renderer {
         )?;
 
         renderer.default_data = Some((default_texture, default_shader, default_uniform.0));
+        renderer.background_data = Some(renderer.build_background()?);
+        renderer.skybox_data = Some(renderer.build_skybox()?);
 
         Ok(renderer)
     }
@@ -181,14 +225,37 @@ impl Renderer {
         self.config.width = new_size.width;
         self.config.height = new_size.height;
         #[cfg(not(feature = "android"))]
-        self.surface
-            .as_ref()
-            .unwrap()
-            .configure(&self.device, &self.config);
+        if let Some(ref surface) = self.surface {
+            surface.configure(&self.device, &self.config);
+        }
         #[cfg(not(feature = "android"))]
         {
-            self.depth_buffer =
-                Self::build_depth_buffer("Depth Buffer", &self.device, &self.config);
+            self.depth_buffer = Self::build_depth_buffer(
+                "Depth Buffer",
+                &self.device,
+                &self.config,
+                self.sample_count,
+            );
+            self.msaa_buffer = Self::build_msaa_buffer(
+                "MSAA Buffer",
+                &self.device,
+                &self.config,
+                self.sample_count,
+            );
+            if self.headless_target.is_some() {
+                self.headless_target = Some(Self::build_color_target(
+                    "Headless Target",
+                    &self.device,
+                    &self.config,
+                ));
+            }
+            if self.frame_capture_target.is_some() {
+                self.frame_capture_target = Some(Self::build_color_target(
+                    "Frame Capture Target",
+                    &self.device,
+                    &self.config,
+                ));
+            }
         }
     }
 
@@ -196,88 +263,609 @@ impl Renderer {
         &mut self,
         objects: &std::collections::HashMap<&'static str, Object>,
         camera: &Camera,
-    ) -> Result<
+    ) -> anyhow::Result<
         Option<(
             wgpu::CommandEncoder,
             wgpu::TextureView,
-            wgpu::SurfaceTexture,
+            Option<wgpu::SurfaceTexture>,
         )>,
-        wgpu::SurfaceError,
     > {
-        let surface = if let Some(ref surface) = self.surface {
-            surface
+        let (frame, view) = if let Some(ref surface) = self.surface {
+            let frame = surface.get_current_texture()?;
+            let view = frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            (Some(frame), view)
+        } else if let Some(ref target) = self.headless_target {
+            (
+                None,
+                target
+                    .0
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+            )
         } else {
             return Ok(None);
         };
 
-        let frame = surface.get_current_texture()?;
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: true,
+        self.update_post_processing()?;
+        // the graph is taken out while it runs, as its passes get to use the renderer
+        let mut render_graph = std::mem::take(&mut self.render_graph);
+        let result =
+            self.execute_render_graph(&mut render_graph, &mut encoder, &view, objects, camera);
+        self.render_graph = render_graph;
+        result?;
+
+        Ok(Some((encoder, view, frame)))
+    }
+
+    /// Returns the number of samples per pixel used for MSAA
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Changes the number of samples per pixel used for MSAA, 1 disables it. The frame targets
+    /// and the pipelines of the objects are rebuilt to match.
+    ///
+    /// Render targets built before the change have to be rebuilt.
+    pub fn set_sample_count(
+        &mut self,
+        sample_count: u32,
+        objects: &mut std::collections::HashMap<&'static str, Object>,
+    ) -> anyhow::Result<()> {
+        Self::validate_sample_count(&self.adapter, sample_count, self.render_format())?;
+        self.sample_count = sample_count;
+
+        self.depth_buffer = Self::build_depth_buffer(
+            "Depth Buffer",
+            &self.device,
+            &self.config,
+            self.sample_count,
+        );
+        self.msaa_buffer =
+            Self::build_msaa_buffer("MSAA Buffer", &self.device, &self.config, self.sample_count);
+
+        self.rebuild_pipelines(objects)
+    }
+
+    /// Returns the HDR settings, if the scene is rendered in HDR
+    pub fn hdr(&self) -> Option<HdrSettings> {
+        self.hdr
+    }
+
+    /// Enables HDR rendering with the given settings, or disables it with `None`. The scene is
+    /// then rendered into a `Renderer::HDR_FORMAT` texture, and tonemapped to the frame after
+    /// the post effects. If the post effects include tonemapping, that is used instead, and
+    /// the effects after it are applied to the tonemapped colors.
+    ///
+    /// Turning HDR on or off rebuilds the pipelines of the objects, and render targets built
+    /// before have to be rebuilt. Changing the settings while it's on is cheap, e.g. to adjust
+    /// the exposure every frame.
+    pub fn set_hdr(
+        &mut self,
+        hdr: Option<HdrSettings>,
+        objects: &mut std::collections::HashMap<&'static str, Object>,
+    ) -> anyhow::Result<()> {
+        let format_changed = hdr.is_some() != self.hdr.is_some();
+        if hdr.is_some() {
+            Self::validate_render_format(&self.adapter, Self::HDR_FORMAT)?;
+            Self::validate_sample_count(&self.adapter, self.sample_count, Self::HDR_FORMAT)?;
+        }
+        self.hdr = hdr;
+
+        if format_changed {
+            self.rebuild_pipelines(objects)?;
+            self.rebuild_post_processing()?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds the default, background and object pipelines, after the sample count or the
+    /// format they render in has changed
+    fn rebuild_pipelines(
+        &mut self,
+        objects: &mut std::collections::HashMap<&'static str, Object>,
+    ) -> anyhow::Result<()> {
+        let default_data = self.default_data.take().unwrap();
+        let default_uniform = self.build_uniform_buffer(&[
+            self.build_uniform_buffer_part("Transformation Matrix", DEFAULT_MATRIX_4),
+            self.build_uniform_buffer_part(
+                "Color",
+                uniform_type::Array4 {
+                    data: DEFAULT_COLOR,
                 },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_buffer.1,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
-                stencil_ops: None,
+            ),
+        ])?;
+        let default_shader = self.build_shader(
+            "Default Shader",
+            DEFAULT_SHADER.to_string(),
+            Some(&default_uniform.1),
+            ShaderSettings::default(),
+        )?;
+        self.default_data = Some((default_data.0, default_shader, default_data.2));
+        self.background_data = Some(self.build_background()?);
+        self.skybox_data = Some(self.build_skybox()?);
+        // pipelines of the old sample count or format can't be used anymore
+        self.pipeline_cache.clear();
+        // the new uniform buffer has to be filled with the current background
+        let background = std::mem::take(&mut self.background);
+        self.set_background(background);
+
+        for object in objects.values_mut() {
+            object.update_shader(self)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the sample count can be used for the color and depth targets
+    pub(crate) fn validate_sample_count(
+        adapter: &wgpu::Adapter,
+        sample_count: u32,
+        format: wgpu::TextureFormat,
+    ) -> anyhow::Result<()> {
+        if sample_count == 0 || sample_count > 32 || !sample_count.is_power_of_two() {
+            anyhow::bail!(
+                "The sample count must be a power of two up to 32, got {}",
+                sample_count
+            );
+        }
+
+        if sample_count > 1 {
+            // downlevel backends are checked against what the adapter reports, same as wgpu does
+            let downlevel = !adapter.get_downlevel_capabilities().is_webgpu_compliant();
+            for format in [format, Self::DEPTH_FORMAT] {
+                let features = if downlevel {
+                    adapter.get_texture_format_features(format)
+                } else {
+                    format.describe().guaranteed_format_features
+                };
+                if !features
+                    .flags
+                    .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE)
+                {
+                    anyhow::bail!("The {:?} format can't be multisampled", format);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the adapter can render to textures of the format
+    pub(crate) fn validate_render_format(
+        adapter: &wgpu::Adapter,
+        format: wgpu::TextureFormat,
+    ) -> anyhow::Result<()> {
+        let features = if adapter.get_downlevel_capabilities().is_webgpu_compliant() {
+            format.describe().guaranteed_format_features
+        } else {
+            adapter.get_texture_format_features(format)
+        };
+        if !features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        {
+            anyhow::bail!(
+                "The {:?} format can't be rendered to on this adapter",
+                format
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Changes what the frames show behind the objects. Can be changed at any time, e.g. from the update loop
+    pub fn set_background(&mut self, background: Background) {
+        let (top, bottom) = match background {
+            Background::Gradient { top, bottom } => (top, bottom),
+            _ => (DEFAULT_COLOR, DEFAULT_COLOR),
+        };
+        self.queue.write_buffer(
+            &self.background_data.as_ref().unwrap().1,
+            0,
+            bytemuck::cast_slice(&[top, bottom]),
+        );
+
+        self.background = background;
+    }
+
+    /// Returns what the frames show behind the objects
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// How the color attachment is loaded at the start of a frame, according to the background
+    pub(crate) fn background_load_op(&self) -> wgpu::LoadOp<wgpu::Color> {
+        match self.background {
+            Background::Color(color) => wgpu::LoadOp::Clear(wgpu::Color {
+                r: color[0] as f64,
+                g: color[1] as f64,
+                b: color[2] as f64,
+                a: color[3] as f64,
             }),
+            Background::DontClear => wgpu::LoadOp::Load,
+            Background::Gradient { .. } | Background::Texture(_) | Background::Skybox(_) => {
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+            }
+        }
+    }
+
+    /// Draws gradient, texture and skybox backgrounds, as a triangle covering the whole frame
+    pub(crate) fn draw_background<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let (texture, background_data) = match self.background {
+            Background::Gradient { .. } => (
+                &self.default_data.as_ref().unwrap().0,
+                self.background_data.as_ref().unwrap(),
+            ),
+            Background::Texture(ref texture) => (texture, self.background_data.as_ref().unwrap()),
+            Background::Skybox(ref cubemap) => {
+                (&cubemap.bind_group, self.skybox_data.as_ref().unwrap())
+            }
+            _ => return,
+        };
+
+        render_pass.set_pipeline(&background_data.0);
+        render_pass.set_bind_group(0, texture, &[]);
+        render_pass.set_bind_group(1, &background_data.2, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Records the background and the objects, one render pass per layer. The depth is cleared
+    /// between the layers, so the higher layers are always drawn on top of the lower ones.
+    pub(crate) fn draw_scene<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        attachment: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth_view: &wgpu::TextureView,
+        objects: impl Iterator<Item = &'a Object>,
+        camera: &Camera,
+    ) {
+        let mut objects: Vec<&Object> = objects.collect();
+        // objects with the same material are drawn one after another within each z index
+        objects.sort_by_key(|object| {
+            (
+                object.layer,
+                object.z_index,
+                object.material,
+                object.creation_order,
+            )
         });
 
-        //? Scissor
-        //render_pass.set_scissor_rect(50, 50, 500, 500);
+        if let Background::Skybox(_) = self.background {
+            self.update_skybox(camera);
+        }
+
+        let mut layers: Vec<&[&Object]> = Vec::new();
+        let mut start = 0;
+        for end in 1..=objects.len() {
+            if end == objects.len() || objects[end].layer != objects[start].layer {
+                layers.push(&objects[start..end]);
+                start = end;
+            }
+        }
+        if layers.is_empty() {
+            layers.push(&[]);
+        }
+
+        for (index, layer) in layers.into_iter().enumerate() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: attachment,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: if index == 0 {
+                            self.background_load_op()
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            //? Scissor
+            //render_pass.set_scissor_rect(50, 50, 500, 500);
 
+            if index == 0 {
+                self.draw_background(&mut render_pass);
+            }
+            self.draw_objects(&mut render_pass, layer.iter().copied(), camera);
+        }
+    }
+
+    /// Records the draw calls of the objects into a render pass, as seen from the camera.
+    ///
+    /// Opaque objects are drawn first, then the transparent ones from back to front.
+    pub(crate) fn draw_objects<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        objects: impl Iterator<Item = &'a Object>,
+        camera: &'a Camera,
+    ) {
         let default_data = self.default_data.as_ref().unwrap();
 
         render_pass.set_bind_group(0, &default_data.0, &[]);
         render_pass.set_pipeline(&default_data.1);
         render_pass.set_bind_group(1, &camera.uniform_data, &[]);
-
-        for i in objects.iter() {
-            let i = i.1;
-            render_pass.set_pipeline(&i.pipeline.shader);
-            render_pass.set_bind_group(0, &i.pipeline.texture, &[]);
+        // only used by the pipelines of objects receiving shadows
+        render_pass.set_bind_group(3, &self.shadow_maps.bind_group, &[]);
+
+        let (opaque, transparent): (Vec<&Object>, Vec<&Object>) =
+            objects.partition(|object| !object.is_transparent());
+        let mut transparent: Vec<(f32, &Object)> = transparent
+            .into_iter()
+            .map(|object| (Self::view_depth(object, camera), object))
+            .collect();
+        // the sort is stable, so objects at the same depth keep their order within the layer
+        transparent.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        // the pipeline and textures are only bound when they change from the previous object
+        let mut bound: Option<(&Shaders, &wgpu::BindGroup)> = None;
+        for i in opaque
+            .into_iter()
+            .chain(transparent.into_iter().map(|(_, object)| object))
+        {
+            let textures = match i.material.and_then(|handle| self.materials.get(handle.0)) {
+                Some(material) if i.shader_settings.material => &material.bind_group,
+                _ => i.pipeline.texture.as_ref(),
+            };
+            if !bound.is_some_and(|(shader, _)| Shaders::ptr_eq(shader, &i.pipeline.shader)) {
+                render_pass.set_pipeline(&i.pipeline.shader);
+            }
+            if !bound.is_some_and(|(_, bound)| std::ptr::eq(bound, textures)) {
+                render_pass.set_bind_group(0, textures, &[]);
+            }
+            bound = Some((&i.pipeline.shader, textures));
             if i.pipeline.uniform.is_some() {
                 render_pass.set_bind_group(2, &i.pipeline.uniform.as_ref().unwrap(), &[]);
             }
             render_pass.set_vertex_buffer(0, i.pipeline.vertex_buffer.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, i.pipeline.instance_buffer.instance_buffer.slice(..));
             render_pass.set_index_buffer(
                 i.pipeline.vertex_buffer.index_buffer.slice(..),
-                wgpu::IndexFormat::Uint16,
+                i.pipeline.vertex_buffer.index_format,
+            );
+            render_pass.draw_indexed(
+                0..i.pipeline.vertex_buffer.length,
+                0,
+                0..i.pipeline.instance_buffer.length,
             );
-            render_pass.draw_indexed(0..i.pipeline.vertex_buffer.length, 0, 0..1);
         }
-        drop(render_pass);
+    }
 
-        Ok(Some((encoder, view, frame)))
+    /// The depth of the object's origin on screen, between 0 (near) and 1 (far).
+    /// Objects behind the camera are treated as the furthest.
+    fn view_depth(object: &Object, camera: &Camera) -> f32 {
+        let origin = object.transformation_matrix * nalgebra_glm::vec4(0.0, 0.0, 0.0, 1.0);
+        if !object.camera_effect {
+            return origin.z;
+        }
+
+        let clip = camera.view_data * origin;
+        if clip.w <= 0.0 {
+            f32::INFINITY
+        } else {
+            clip.z / clip.w
+        }
+    }
+
+    /// Renders the objects into a render target as seen from the given camera. If `only` is given,
+    /// just the objects with those names are rendered.
+    ///
+    /// Objects that use the target's own texture must not be rendered into it.
+    pub fn render_to_target(
+        &mut self,
+        target: &RenderTarget,
+        objects: &std::collections::HashMap<&'static str, Object>,
+        camera: &mut Camera,
+        only: Option<&[&'static str]>,
+    ) -> anyhow::Result<()> {
+        camera.update_view_projection(self)?;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Target Encoder"),
+            });
+
+        let (attachment, resolve_target) = match target.msaa {
+            Some(ref msaa) => (&msaa.1, Some(&target.color.1)),
+            None => (&target.color.1, None),
+        };
+
+        let objects = objects
+            .iter()
+            .filter(|(name, _)| only.map_or(true, |only| only.contains(*name)))
+            .map(|(_, object)| object);
+        self.draw_scene(
+            &mut encoder,
+            attachment,
+            resolve_target,
+            &target.depth.1,
+            objects,
+            camera,
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
     }
 
     pub(crate) fn render(
         &mut self,
-        encoder: wgpu::CommandEncoder,
-        frame: wgpu::SurfaceTexture,
+        mut encoder: wgpu::CommandEncoder,
+        frame: Option<wgpu::SurfaceTexture>,
     ) -> Result<(), wgpu::SurfaceError> {
+        // keep a copy of the frame before it's presented, so it can be captured later
+        if let (Some(frame), Some(target)) = (frame.as_ref(), self.frame_capture_target.as_ref()) {
+            encoder.copy_texture_to_texture(
+                frame.texture.as_image_copy(),
+                target.0.as_image_copy(),
+                wgpu::Extent3d {
+                    width: self.config.width,
+                    height: self.config.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
+        // headless renders have nothing to present
+        if let Some(frame) = frame {
+            frame.present();
+        }
 
         Ok(())
     }
+
+    /// Allows frames of a windowed renderer to be captured. The surface has to be reconfigured
+    /// to allow copies and each frame is copied once before it's presented, so this is off by default.
+    ///
+    /// Headless renderers can always be captured.
+    pub fn set_frame_capture(&mut self, enabled: bool) {
+        if enabled {
+            self.config.usage |= wgpu::TextureUsages::COPY_SRC;
+            self.frame_capture_target = Some(Self::build_color_target(
+                "Frame Capture Target",
+                &self.device,
+                &self.config,
+            ));
+        } else {
+            self.config.usage -= wgpu::TextureUsages::COPY_SRC;
+            self.frame_capture_target = None;
+        }
+
+        if let Some(ref surface) = self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+    }
+
+    /// Reads the last rendered frame back from the GPU into an image. Like the window, the
+    /// captured frame is fully opaque.
+    ///
+    /// Works on headless renderers, and on windowed renderers once `set_frame_capture` is enabled.
+    pub fn capture_frame(&self) -> anyhow::Result<image::RgbaImage> {
+        let target = match (&self.headless_target, &self.frame_capture_target) {
+            (Some(target), _) | (None, Some(target)) => &target.0,
+            (None, None) => anyhow::bail!(
+                "There is no frame to capture, enable frame capture with set_frame_capture first"
+            ),
+        };
+
+        let mut frame = self.read_texture(
+            target,
+            self.config.width,
+            self.config.height,
+            self.config.format,
+        )?;
+        frame.pixels_mut().for_each(|pixel| pixel.0[3] = 255);
+
+        Ok(frame)
+    }
+
+    /// Captures the last rendered frame and saves it as an image file. The format is picked from the extension.
+    pub fn save_screenshot<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
+        self.capture_frame()?.save(path)?;
+
+        Ok(())
+    }
+
+    /// Reads the color texture of a render target back from the GPU into an image
+    pub fn capture_render_target(&self, target: &RenderTarget) -> anyhow::Result<image::RgbaImage> {
+        self.read_texture(&target.color.0, target.size.0, target.size.1, target.format)
+    }
+
+    /// Copies a 2D color texture to a mapped buffer and reads it back as an RGBA image
+    pub(crate) fn read_texture(
+        &self,
+        texture: &wgpu::Texture,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let swap_red_and_blue = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => anyhow::bail!("Capturing textures of {:?} format is not supported", format),
+        };
+
+        // rows of a texture copy have to be aligned to 256 bytes
+        let unpadded_bytes_per_row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut pixels = Vec::<u8>::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        if swap_red_and_blue {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Captured data doesn't match the frame size"))
+    }
 }
@@ -0,0 +1,502 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::header::{
+    Camera, Object, RenderGraph, RenderGraphContext, RenderGraphName, RenderGraphPass,
+    RenderGraphTexture, RenderGraphTextureData, RenderGraphTextureSize, Renderer, Textures,
+};
+use anyhow::Context;
+
+impl RenderGraph {
+    /// The frame that is presented to the window, or captured on headless engines
+    pub const FRAME: &'static str = "frame";
+    /// The depth buffer of the frame
+    pub const DEPTH: &'static str = "depth";
+    /// The name of the pass that draws the background and the objects
+    pub const SCENE: &'static str = "scene";
+    /// The texture the scene is drawn into instead of the frame, when there are post effects
+    pub const SCENE_COLOR: &'static str = "scene color";
+    /// The name of the pass that renders the shadow maps, first in the graph while a light casts shadows
+    pub const SHADOWS: &'static str = "shadows";
+
+    /// Creates a graph with just the scene pass, drawing to the frame, which is the graph the
+    /// renderer starts with. `RenderGraph::default()` is empty instead, to build a graph from scratch
+    pub fn new() -> Self {
+        Self {
+            passes: vec![RenderGraphPass::scene(Self::FRAME, Self::DEPTH)],
+            ..Default::default()
+        }
+    }
+
+    /// Adds a pass to the graph. Its place in the frame is decided by the textures it reads and writes
+    pub fn add_pass(&mut self, pass: RenderGraphPass) -> anyhow::Result<()> {
+        if self.passes.iter().any(|i| i.name == pass.name) {
+            anyhow::bail!("The render graph already has a pass named {}", pass.name);
+        }
+        self.passes.push(pass);
+
+        Ok(())
+    }
+
+    /// Removes a pass from the graph and returns it
+    pub fn remove_pass(&mut self, name: &str) -> Option<RenderGraphPass> {
+        let index = self.passes.iter().position(|i| i.name == name)?;
+        Some(self.passes.remove(index))
+    }
+
+    /// Returns a pass of the graph, to change what it reads or writes
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut RenderGraphPass> {
+        self.passes.iter_mut().find(|i| i.name == name)
+    }
+
+    /// Sets how a texture used by the passes is allocated. Textures that aren't described
+    /// are the same size and format as the frame.
+    ///
+    /// The frame and its depth buffer are owned by the renderer and can't be described.
+    pub fn set_texture(
+        &mut self,
+        name: impl Into<RenderGraphName>,
+        descriptor: RenderGraphTexture,
+    ) -> anyhow::Result<()> {
+        let name = name.into();
+        if name == Self::FRAME || name == Self::DEPTH {
+            anyhow::bail!("The {} texture is owned by the renderer", name);
+        }
+        self.descriptors.insert(name, descriptor);
+
+        Ok(())
+    }
+
+    /// Returns the names of the passes in the order they are run
+    pub fn order(&self) -> anyhow::Result<Vec<&str>> {
+        Ok(self
+            .sorted_passes()?
+            .into_iter()
+            .map(|i| self.passes[i].name.as_ref())
+            .collect())
+    }
+
+    /// The indices of the passes in the order they are run, which is the order they were added in,
+    /// moved as needed so that each texture is written before it's read
+    pub(crate) fn sorted_passes(&self) -> anyhow::Result<Vec<usize>> {
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for input in pass.inputs.iter() {
+                if pass.writes(input) {
+                    anyhow::bail!(
+                        "The {} pass reads and writes the {} texture at the same time",
+                        pass.name,
+                        input
+                    );
+                }
+                if *input == Self::FRAME {
+                    anyhow::bail!(
+                        "The {} pass reads the frame, which can't be read. Render to a texture instead",
+                        pass.name
+                    );
+                }
+
+                let before = self.writers(input).collect::<Vec<usize>>();
+                if before.is_empty() {
+                    anyhow::bail!(
+                        "The {} texture read by the {} pass isn't written by any pass",
+                        input,
+                        pass.name
+                    );
+                }
+                dependencies[index].extend(before);
+            }
+
+            for output in pass.outputs() {
+                dependencies[index].extend(self.writers(output).filter(|i| *i < index));
+            }
+        }
+
+        let mut done = vec![false; self.passes.len()];
+        let mut order = Vec::with_capacity(self.passes.len());
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|i| !done[*i] && dependencies[*i].iter().all(|dependency| done[*dependency]));
+
+            match next {
+                Some(index) => {
+                    done[index] = true;
+                    order.push(index);
+                }
+                None => anyhow::bail!(
+                    "The passes of the render graph depend on each other in a cycle: {}",
+                    (0..self.passes.len())
+                        .filter(|i| !done[*i])
+                        .map(|i| self.passes[i].name.as_ref())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+            }
+        }
+
+        Ok(order)
+    }
+
+    fn writers<'a>(&'a self, texture: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.passes
+            .iter()
+            .enumerate()
+            .filter(move |(_, pass)| pass.writes(texture))
+            .map(|(index, _)| index)
+    }
+}
+
+impl RenderGraphPass {
+    /// Creates a pass that neither reads nor writes any texture. Those are set on the returned pass
+    pub fn new(
+        name: impl Into<RenderGraphName>,
+        execute: impl FnMut(&mut RenderGraphContext) -> anyhow::Result<()> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            inputs: Vec::new(),
+            color_outputs: Vec::new(),
+            depth_output: None,
+            execute: Box::new(execute),
+        }
+    }
+
+    /// Creates the pass that draws the background and the objects into the given textures.
+    /// It keeps drawing into its first color output and its depth output when they are changed
+    pub fn scene(color: impl Into<RenderGraphName>, depth: impl Into<RenderGraphName>) -> Self {
+        Self {
+            color_outputs: vec![color.into()],
+            depth_output: Some(depth.into()),
+            ..Self::new(RenderGraph::SCENE, |context| {
+                match (context.color_outputs.first(), context.depth_output) {
+                    (Some(color), Some(depth)) => context.draw_scene(color, depth),
+                    _ => anyhow::bail!("The scene needs a color and a depth output to draw into"),
+                }
+            })
+        }
+    }
+
+    /// The color and depth textures the pass writes to
+    pub fn outputs(&self) -> impl Iterator<Item = &str> {
+        self.color_outputs
+            .iter()
+            .map(|i| i.as_ref())
+            .chain(self.depth_output.as_deref())
+    }
+
+    fn writes(&self, texture: &str) -> bool {
+        self.outputs().any(|i| i == texture)
+    }
+}
+
+impl<'a> RenderGraphContext<'a> {
+    /// The name of the pass being recorded
+    pub fn pass(&self) -> &'a str {
+        self.pass
+    }
+
+    /// The view of a texture the pass reads
+    pub fn input(&self, name: &str) -> anyhow::Result<&'a wgpu::TextureView> {
+        self.check_declared(name, self.reads.iter().any(|i| i == name), "read")?;
+
+        if name == RenderGraph::DEPTH {
+            Ok(&self.renderer.depth_buffer.1)
+        } else {
+            Ok(&self.texture(name)?.texture.1)
+        }
+    }
+
+    /// The bind group of a color texture the pass reads, to be sampled in shaders
+    /// through the texture bind group layout of the renderer
+    pub fn input_bind_group(&self, name: &str) -> anyhow::Result<&'a Textures> {
+        self.check_declared(name, self.reads.iter().any(|i| i == name), "read")?;
+
+        self.texture(name)?.bind_group.as_ref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "The {} texture can't be sampled with a filtering sampler",
+                name
+            )
+        })
+    }
+
+    /// The view to render a color output of the pass to, and the view to resolve it to when multisampled
+    pub fn color_attachment(
+        &self,
+        name: &str,
+    ) -> anyhow::Result<(&'a wgpu::TextureView, Option<&'a wgpu::TextureView>)> {
+        self.check_declared(
+            name,
+            self.color_outputs.iter().any(|i| i == name),
+            "write color to",
+        )?;
+
+        if name == RenderGraph::FRAME {
+            return Ok(match self.renderer.msaa_buffer {
+                Some(ref msaa) => (&msaa.1, Some(self.frame)),
+                None => (self.frame, None),
+            });
+        }
+
+        let texture = self.texture(name)?;
+        Ok(match texture.msaa {
+            Some(ref msaa) => (&msaa.1, Some(&texture.texture.1)),
+            None => (&texture.texture.1, None),
+        })
+    }
+
+    /// The view to render the depth output of the pass to
+    pub fn depth_attachment(&self, name: &str) -> anyhow::Result<&'a wgpu::TextureView> {
+        self.check_declared(name, self.depth_output == Some(name), "write depth to")?;
+
+        if name == RenderGraph::DEPTH {
+            Ok(&self.renderer.depth_buffer.1)
+        } else {
+            Ok(&self.texture(name)?.texture.1)
+        }
+    }
+
+    /// The size of a texture of the graph in pixels, as width and height
+    pub fn size(&self, name: &str) -> anyhow::Result<(u32, u32)> {
+        if name == RenderGraph::FRAME || name == RenderGraph::DEPTH {
+            Ok((self.renderer.config.width, self.renderer.config.height))
+        } else {
+            Ok(self.texture(name)?.size)
+        }
+    }
+
+    /// Draws the background and the objects as seen from the camera, same as the scene pass does
+    pub fn draw_scene(&mut self, color: &str, depth: &str) -> anyhow::Result<()> {
+        let (attachment, resolve_target) = self.color_attachment(color)?;
+        let depth_view = self.depth_attachment(depth)?;
+
+        self.renderer.draw_scene(
+            self.encoder,
+            attachment,
+            resolve_target,
+            depth_view,
+            self.objects.values(),
+            self.camera,
+        );
+
+        Ok(())
+    }
+
+    fn check_declared(&self, name: &str, declared: bool, access: &str) -> anyhow::Result<()> {
+        if !declared {
+            anyhow::bail!(
+                "The {} pass didn't declare that it would {} the {} texture",
+                self.pass,
+                access,
+                name
+            );
+        }
+
+        Ok(())
+    }
+
+    fn texture(&self, name: &str) -> anyhow::Result<&'a RenderGraphTextureData> {
+        self.textures
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("The render graph has no texture named {}", name))
+    }
+}
+
+impl Renderer {
+    /// Records the passes of the render graph into the encoder, with the given view as the frame
+    pub(crate) fn execute_render_graph(
+        &self,
+        graph: &mut RenderGraph,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &wgpu::TextureView,
+        objects: &std::collections::HashMap<&'static str, Object>,
+        camera: &Camera,
+    ) -> anyhow::Result<()> {
+        let order = graph.sorted_passes()?;
+        self.allocate_render_graph_textures(graph);
+
+        let RenderGraph {
+            passes, textures, ..
+        } = graph;
+        for index in order {
+            let pass = &mut passes[index];
+            let mut context = RenderGraphContext {
+                encoder: &mut *encoder,
+                renderer: self,
+                objects,
+                camera,
+                pass: &pass.name,
+                reads: &pass.inputs,
+                color_outputs: &pass.color_outputs,
+                depth_output: pass.depth_output.as_deref(),
+                frame,
+                textures,
+            };
+
+            (pass.execute)(&mut context)
+                .with_context(|| format!("The {} pass of the render graph failed", pass.name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure every texture used by the passes exists with its current size and format.
+    /// Textures that are no longer used are dropped
+    fn allocate_render_graph_textures(&self, graph: &mut RenderGraph) {
+        let mut used = std::collections::HashSet::new();
+
+        for pass in graph.passes.iter() {
+            let names = pass
+                .inputs
+                .iter()
+                .chain(pass.color_outputs.iter())
+                .chain(pass.depth_output.iter());
+            for name in names {
+                if name == RenderGraph::FRAME || name == RenderGraph::DEPTH || !used.insert(name) {
+                    continue;
+                }
+
+                let descriptor = graph.descriptors.get(name).copied().unwrap_or_default();
+                let depth = graph
+                    .passes
+                    .iter()
+                    .any(|i| i.depth_output.as_ref() == Some(name));
+                let format = descriptor.format.unwrap_or(if depth {
+                    Self::DEPTH_FORMAT
+                } else {
+                    self.render_format()
+                });
+                let size = match descriptor.size {
+                    RenderGraphTextureSize::Frame => (self.config.width, self.config.height),
+                    RenderGraphTextureSize::Scaled(scale) => (
+                        ((self.config.width as f32 * scale).round() as u32).max(1),
+                        ((self.config.height as f32 * scale).round() as u32).max(1),
+                    ),
+                    RenderGraphTextureSize::Fixed(width, height) => (width, height),
+                };
+                let sample_count = if descriptor.multisampled {
+                    self.sample_count
+                } else {
+                    1
+                };
+
+                let up_to_date = graph.textures.get(name).is_some_and(|texture| {
+                    texture.size == size
+                        && texture.format == format
+                        && texture.sample_count == sample_count
+                });
+                if !up_to_date {
+                    graph.textures.insert(
+                        name.clone(),
+                        self.build_render_graph_texture(name, size, format, sample_count, depth),
+                    );
+                }
+            }
+        }
+
+        graph.textures.retain(|name, _| used.contains(name));
+    }
+
+    fn build_render_graph_texture(
+        &self,
+        name: &str,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth: bool,
+    ) -> RenderGraphTextureData {
+        let build = |label: &str, sample_count: u32, usage: wgpu::TextureUsages| {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            (texture, view)
+        };
+
+        // depth can't be resolved, so a multisampled depth texture is used as it is
+        let (texture, msaa) = if depth || sample_count <= 1 {
+            (
+                build(
+                    name,
+                    sample_count,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                ),
+                None,
+            )
+        } else {
+            (
+                build(
+                    name,
+                    1,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                ),
+                Some(build(
+                    format!("{} MSAA", name).as_str(),
+                    sample_count,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                )),
+            )
+        };
+
+        let filterable = matches!(
+            format.describe().sample_type,
+            wgpu::TextureSampleType::Float { filterable: true }
+        );
+        let bind_group = if filterable && (msaa.is_some() || sample_count <= 1) {
+            let sampler = self.device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            });
+
+            Some(std::sync::Arc::new(self.device.create_bind_group(
+                &wgpu::BindGroupDescriptor {
+                    layout: &self.texture_bind_group_layout,
+                    label: Some("Render Graph Texture Bind Group"),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&texture.1),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                },
+            )))
+        } else {
+            None
+        };
+
+        RenderGraphTextureData {
+            texture,
+            msaa,
+            bind_group,
+            size,
+            format,
+            sample_count,
+        }
+    }
+}
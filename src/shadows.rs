@@ -0,0 +1,773 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::{
+    header::{
+        Camera, IndexFormat, InstanceRaw, Light, LightKind, Object, RenderGraph, RenderGraphPass,
+        Renderer, ShaderPrimitive, Shaders, ShadowMaps, ShadowSettings, UniformBuffers,
+        VertexLayoutDescriptor,
+    },
+    objects::{instance_input, position_expression, wgsl_type},
+    utils::{
+        default_resources::{DEFAULT_MATRIX_4, SHADOW_CASTER_SHADER},
+        light::{light_buffer_size, DEFAULT_MAX_LIGHTS},
+    },
+};
+use nalgebra_glm::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+/// The most lights that can cast shadows at once
+pub const MAX_SHADOW_CASTERS: usize = 4;
+/// Amount of layers a light can take in the shadow maps, enough for the six faces around a point light
+const SHADOW_MAP_LAYERS: usize = 6;
+/// Amount of layers all the lights can take together
+const MAX_SHADOW_LAYERS: usize = MAX_SHADOW_CASTERS * SHADOW_MAP_LAYERS;
+/// How close to point and spot lights their shadow maps start
+const SHADOW_NEAR: f32 = 0.05;
+
+/// A light casting shadows, as it's laid out in `shadow_uniforms_shader`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct ShadowCasterData {
+    light: [f32; 4],
+    cascade_splits: [f32; 4],
+    layers: [f32; 4],
+    settings: [f32; 4],
+}
+
+/// The uniforms of `SHADOW_SHADER`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+struct ShadowUniforms {
+    matrices: [[[f32; 4]; 4]; MAX_SHADOW_LAYERS],
+    casters: [ShadowCasterData; MAX_SHADOW_CASTERS],
+    camera_position: [f32; 4],
+    camera_forward: [f32; 4],
+}
+
+/// Declares the uniforms of `SHADOW_SHADER`, with the arrays sized like `ShadowUniforms`
+pub(crate) fn shadow_uniforms_shader() -> String {
+    format!(
+        r#"
+struct ShadowCaster {{
+    // position and kind of the light
+    light: vec4<f32>,
+    // where each cascade of a directional light ends
+    cascade_splits: vec4<f32>,
+    // first layer and amount of layers
+    layers: vec4<f32>,
+    // bias, normal bias and PCF radius
+    settings: vec4<f32>,
+}};
+struct ShadowUniforms {{
+    matrices: array<mat4x4<f32>, {}>,
+    casters: array<ShadowCaster, {}>,
+    camera_position: vec4<f32>,
+    camera_forward: vec4<f32>,
+}};"#,
+        MAX_SHADOW_LAYERS, MAX_SHADOW_CASTERS
+    )
+}
+
+/// Amount of layers of the shadow texture holding the layers of the lights. The GL backend
+/// takes textures with a single layer as plain 2D textures, and square textures with a multiple
+/// of six layers as cube maps, neither of which can be bound as an array, so there may be a
+/// spare layer
+fn texture_layers(layers: usize) -> u32 {
+    let layers = layers.max(2);
+    if layers % 6 == 0 {
+        layers as u32 + 1
+    } else {
+        layers as u32
+    }
+}
+
+type ShadowCasterKey = (
+    VertexLayoutDescriptor,
+    usize,
+    ShaderPrimitive,
+    Option<IndexFormat>,
+);
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 1024,
+            cascades: 3,
+            distance: 50.0,
+            bias: 0.002,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+impl Renderer {
+    pub(crate) fn build_lighting_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Creates shadow maps of a single texel, which are resized once a light casts shadows
+    pub(crate) fn build_shadow_maps(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_layout: &wgpu::BindGroupLayout,
+    ) -> ShadowMaps {
+        // linear filtering compares the four nearest texels, which smooths the PCF further
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Uniform"),
+            contents: bytemuck::cast_slice(&[<ShadowUniforms as bytemuck::Zeroable>::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let light_buffer = Self::build_light_buffer(device, DEFAULT_MAX_LIGHTS);
+        let layer_uniforms = (0..MAX_SHADOW_LAYERS)
+            .map(|_| {
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shadow Caster Uniform"),
+                    contents: bytemuck::cast_slice(&[DEFAULT_MATRIX_4]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Shadow Caster Bind Group"),
+                    layout: uniform_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+        let (texture, layers, bind_group) = Self::build_shadow_texture(
+            device,
+            layout,
+            &sampler,
+            &uniform_buffer,
+            &light_buffer,
+            1,
+            0,
+        );
+
+        ShadowMaps {
+            texture,
+            layers,
+            resolution: 1,
+            sampler,
+            uniform_buffer,
+            light_buffer,
+            max_lights: DEFAULT_MAX_LIGHTS,
+            bind_group,
+            layer_uniforms,
+            active_layers: 0,
+            casters: Vec::new(),
+            ignored_casters: Vec::new(),
+            pipelines: std::collections::HashMap::new(),
+        }
+    }
+
+    fn build_light_buffer(device: &wgpu::Device, max_lights: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Uniform"),
+            size: light_buffer_size(max_lights),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Makes room for the given amount of lights in the light buffer. Returns whether the buffer
+    /// was replaced, after which the shaders of the lit objects have to be rebuilt for its size
+    pub(crate) fn resize_light_buffer(&mut self, max_lights: usize) -> anyhow::Result<bool> {
+        if max_lights == self.shadow_maps.max_lights {
+            return Ok(false);
+        }
+        let max_size = self.device.limits().max_uniform_buffer_binding_size as wgpu::BufferAddress;
+        if max_lights == 0 || light_buffer_size(max_lights) > max_size {
+            anyhow::bail!(
+                "LightManager::max_lights must be between 1 and {} on this device, got {}",
+                (max_size - light_buffer_size(0)) / (light_buffer_size(1) - light_buffer_size(0)),
+                max_lights
+            );
+        }
+
+        self.shadow_maps.light_buffer = Self::build_light_buffer(&self.device, max_lights);
+        self.shadow_maps.max_lights = max_lights;
+        // the bind group holds the old buffer
+        let (texture, layers, bind_group) = Self::build_shadow_texture(
+            &self.device,
+            &self.lighting_bind_group_layout,
+            &self.shadow_maps.sampler,
+            &self.shadow_maps.uniform_buffer,
+            &self.shadow_maps.light_buffer,
+            self.shadow_maps.resolution,
+            self.shadow_maps.layers.len(),
+        );
+        self.shadow_maps.texture = texture;
+        self.shadow_maps.layers = layers;
+        self.shadow_maps.bind_group = bind_group;
+
+        Ok(true)
+    }
+
+    fn build_shadow_texture(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+        light_buffer: &wgpu::Buffer,
+        resolution: u32,
+        layer_count: usize,
+    ) -> (wgpu::Texture, Vec<wgpu::TextureView>, UniformBuffers) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: texture_layers(layer_count),
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let layers = (0..layer_count as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Maps"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: light_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (texture, layers, bind_group)
+    }
+
+    /// Prepares the shadow maps of the lights at the given positions for the next frame, and
+    /// adds the pass rendering them to the render graph. The index of each light among them is
+    /// the index of its caster in `SHADOW_SHADER`. Without lights casting shadows the pass is
+    /// removed.
+    ///
+    /// The objects of the `lights` and the objects not affected by the camera don't cast shadows.
+    pub(crate) fn update_shadow_maps(
+        &mut self,
+        lights_with_shadows: &[(Light, [f32; 3])],
+        camera: &Camera,
+        objects: &std::collections::HashMap<&'static str, Object>,
+        lights: &[&'static str],
+    ) -> anyhow::Result<()> {
+        let shadow_lights: Vec<(Light, Vec3, ShadowSettings)> = lights_with_shadows
+            .iter()
+            .take(MAX_SHADOW_CASTERS)
+            .filter_map(|(light, position)| {
+                light
+                    .shadows
+                    .map(|settings| (*light, nalgebra_glm::make_vec3(position), settings))
+            })
+            .collect();
+        if shadow_lights.is_empty() {
+            self.shadow_maps.active_layers = 0;
+            self.shadow_maps.casters.clear();
+            self.queue.write_buffer(
+                &self.shadow_maps.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[<ShadowUniforms as bytemuck::Zeroable>::zeroed()]),
+            );
+            self.render_graph.remove_pass(RenderGraph::SHADOWS);
+            return Ok(());
+        }
+
+        let max_resolution = self.device.limits().max_texture_dimension_2d;
+        for (_, _, settings) in shadow_lights.iter() {
+            if settings.resolution == 0 || settings.resolution > max_resolution {
+                anyhow::bail!(
+                    "The shadow resolution must be between 1 and {}, got {}",
+                    max_resolution,
+                    settings.resolution
+                );
+            }
+            if settings.cascades == 0 || settings.cascades > 4 {
+                anyhow::bail!(
+                    "Directional lights can have 1 to 4 cascades, got {}",
+                    settings.cascades
+                );
+            }
+        }
+        // the shadow maps share a texture, so they share the largest resolution
+        let resolution = shadow_lights
+            .iter()
+            .map(|(_, _, settings)| settings.resolution)
+            .max()
+            .unwrap_or(1);
+
+        let mut uniforms = ShadowUniforms {
+            matrices: [DEFAULT_MATRIX_4.data; MAX_SHADOW_LAYERS],
+            casters: [<ShadowCasterData as bytemuck::Zeroable>::zeroed(); MAX_SHADOW_CASTERS],
+            camera_position: [camera.position.x, camera.position.y, camera.position.z, 1.0],
+            camera_forward: {
+                let forward = camera_forward(camera);
+                [forward.x, forward.y, forward.z, 0.0]
+            },
+        };
+        // the layers of each light follow the layers of the one before it
+        let mut layer_count = 0;
+        for (caster, (light, position, settings)) in
+            uniforms.casters.iter_mut().zip(shadow_lights.iter())
+        {
+            let settings = ShadowSettings {
+                resolution,
+                ..*settings
+            };
+            let (matrices, cascade_splits, kind) = match light.kind {
+                LightKind::Directional { direction } => {
+                    let (matrices, splits) =
+                        cascade_matrices(nalgebra_glm::make_vec3(&direction), &settings, camera);
+                    (matrices, splits, 0.0)
+                }
+                LightKind::Point => (point_matrices(*position, &settings), [0.0; 4], 1.0),
+                LightKind::Spot { direction, angle } => (
+                    vec![spot_matrix(
+                        *position,
+                        nalgebra_glm::make_vec3(&direction),
+                        angle,
+                        &settings,
+                    )],
+                    [0.0; 4],
+                    2.0,
+                ),
+            };
+
+            *caster = ShadowCasterData {
+                light: [position.x, position.y, position.z, kind],
+                cascade_splits,
+                layers: [layer_count as f32, matrices.len() as f32, 0.0, 0.0],
+                settings: [
+                    settings.bias,
+                    settings.normal_bias,
+                    settings.pcf_radius as f32,
+                    0.0,
+                ],
+            };
+            for matrix in matrices.iter() {
+                uniforms.matrices[layer_count] = (*matrix).into();
+                self.queue.write_buffer(
+                    &self.shadow_maps.layer_uniforms[layer_count].0,
+                    0,
+                    bytemuck::cast_slice(&[uniforms.matrices[layer_count]]),
+                );
+                layer_count += 1;
+            }
+        }
+        self.queue.write_buffer(
+            &self.shadow_maps.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniforms]),
+        );
+
+        if resolution != self.shadow_maps.resolution || layer_count != self.shadow_maps.layers.len()
+        {
+            let (texture, layers, bind_group) = Self::build_shadow_texture(
+                &self.device,
+                &self.lighting_bind_group_layout,
+                &self.shadow_maps.sampler,
+                &self.shadow_maps.uniform_buffer,
+                &self.shadow_maps.light_buffer,
+                resolution,
+                layer_count,
+            );
+            self.shadow_maps.texture = texture;
+            self.shadow_maps.layers = layers;
+            self.shadow_maps.bind_group = bind_group;
+            self.shadow_maps.resolution = resolution;
+        }
+
+        self.shadow_maps.casters.clear();
+        for object in objects.values() {
+            if !object.cast_shadows
+                || !object.camera_effect
+                || object.pipeline.uniform.is_none()
+                || lights.contains(&object.name)
+                || object.vertex_layout.format_of("position").is_none()
+            {
+                continue;
+            }
+            let key = Self::shadow_caster_key(object);
+            if !self.shadow_maps.pipelines.contains_key(&key) {
+                let pipeline = self.build_shadow_caster_pipeline(object);
+                self.shadow_maps.pipelines.insert(key, pipeline);
+            }
+            self.shadow_maps.casters.push(object.name);
+        }
+        self.shadow_maps.active_layers = layer_count;
+
+        if self.render_graph.pass_mut(RenderGraph::SHADOWS).is_none() {
+            // nothing depends on the pass through textures, so being first keeps it before the scene
+            self.render_graph.passes.insert(
+                0,
+                RenderGraphPass::new(RenderGraph::SHADOWS, |context| {
+                    context
+                        .renderer
+                        .draw_shadow_maps(context.encoder, context.objects);
+                    Ok(())
+                }),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn shadow_caster_key(object: &Object) -> ShadowCasterKey {
+        (
+            object.vertex_layout.clone(),
+            object.uniform_buffers.len(),
+            object.shader_settings.topology,
+            object.shader_settings.strip_index_format,
+        )
+    }
+
+    /// A depth only pipeline that draws objects with the vertex layout and uniforms of the object
+    fn build_shadow_caster_pipeline(&self, object: &Object) -> Shaders {
+        let vertex_layout = &object.vertex_layout;
+        let (location, format) = vertex_layout
+            .names
+            .iter()
+            .zip(vertex_layout.attributes.iter())
+            .find(|(name, _)| **name == "position")
+            .map(|(_, attribute)| (attribute.shader_location, attribute.format))
+            .unwrap_or((0, wgpu::VertexFormat::Float32x3));
+
+        let shader_source = format!(
+            "{}\nstruct VertexInput {{\n    @location({}) position: {},\n}};\n\nstruct InstanceInput {{\n{}\n}};\n{}\n    return shadow_caster.light_matrix * (transform_uniform.transform_matrix * (instance_matrix * {}));\n}}\n",
+            SHADOW_CASTER_SHADER,
+            location,
+            wgsl_type(format),
+            instance_input(vertex_layout.next_location()),
+            r#"
+@vertex
+fn vs_main(input: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let instance_matrix = mat4x4<f32>(
+        instance.transform_matrix_0,
+        instance.transform_matrix_1,
+        instance.transform_matrix_2,
+        instance.transform_matrix_3,
+    );"#,
+            position_expression(vertex_layout)
+        );
+
+        let shader = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Shadow Caster Shader"),
+                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+            });
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Caster Pipeline Layout"),
+                bind_group_layouts: &[
+                    &self.default_uniform_bind_group_layout,
+                    &object.uniform_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let instance_attributes = InstanceRaw::attributes(vertex_layout.next_location());
+        let instance_layout = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &instance_attributes,
+        };
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Shadow Caster"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[vertex_layout.desc(), instance_layout],
+                },
+                fragment: None,
+                // both sides are drawn, so open meshes cast shadows from behind as well
+                primitive: wgpu::PrimitiveState {
+                    topology: object.shader_settings.topology,
+                    strip_index_format: object.shader_settings.strip_index_format,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Self::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        std::sync::Arc::new(pipeline)
+    }
+
+    /// Renders the depth of the casters into each layer of the shadow maps that is in use
+    pub(crate) fn draw_shadow_maps(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        objects: &std::collections::HashMap<&'static str, Object>,
+    ) {
+        let shadow_maps = &self.shadow_maps;
+        for (view, (_, layer_uniform)) in shadow_maps
+            .layers
+            .iter()
+            .zip(shadow_maps.layer_uniforms.iter())
+            .take(shadow_maps.active_layers)
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_bind_group(0, layer_uniform, &[]);
+
+            for object in shadow_maps.casters.iter().filter_map(|i| objects.get(i)) {
+                let (pipeline, uniform) = match (
+                    shadow_maps.pipelines.get(&Self::shadow_caster_key(object)),
+                    object.pipeline.uniform.as_ref(),
+                ) {
+                    (Some(pipeline), Some(uniform)) => (pipeline, uniform),
+                    _ => continue,
+                };
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(1, uniform, &[]);
+                render_pass
+                    .set_vertex_buffer(0, object.pipeline.vertex_buffer.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(
+                    1,
+                    object.pipeline.instance_buffer.instance_buffer.slice(..),
+                );
+                render_pass.set_index_buffer(
+                    object.pipeline.vertex_buffer.index_buffer.slice(..),
+                    object.pipeline.vertex_buffer.index_format,
+                );
+                render_pass.draw_indexed(
+                    0..object.pipeline.vertex_buffer.length,
+                    0,
+                    0..object.pipeline.instance_buffer.length,
+                );
+            }
+        }
+    }
+}
+
+/// The direction the camera looks in
+fn camera_forward(camera: &Camera) -> Vec3 {
+    let target = if camera.add_position_and_target {
+        camera.position + camera.target
+    } else {
+        camera.target
+    };
+    nalgebra_glm::normalize(&(target - camera.position))
+}
+
+/// An up vector for looking in the direction, which can't be parallel to it
+fn up_for(direction: &Vec3) -> Vec3 {
+    if nalgebra_glm::normalize(direction).y.abs() > 0.99 {
+        nalgebra_glm::vec3(0.0, 0.0, 1.0)
+    } else {
+        nalgebra_glm::vec3(0.0, 1.0, 0.0)
+    }
+}
+
+/// Splits the view of the camera into cascades, and fits an orthographic projection along the
+/// light around each of them. Returns the projections and the distance each cascade ends at
+fn cascade_matrices(
+    direction: Vec3,
+    settings: &ShadowSettings,
+    camera: &Camera,
+) -> (Vec<Mat4>, [f32; 4]) {
+    let direction = nalgebra_glm::normalize(&direction);
+    let up = up_for(&direction);
+
+    // the corners of the view are found from the matrix, so any projection of the camera works
+    let inverse = nalgebra_glm::inverse(&camera.view_data);
+    let unproject = |x: f32, y: f32, z: f32| {
+        let point = inverse * nalgebra_glm::vec4(x, y, z, 1.0);
+        nalgebra_glm::vec3(point.x, point.y, point.z) / point.w
+    };
+    let corner_rays: Vec<(Vec3, Vec3)> = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+        .iter()
+        .map(|(x, y)| (unproject(*x, *y, -1.0), unproject(*x, *y, 1.0)))
+        .collect();
+    let corners_at = |distance: f32| {
+        let fraction = (distance - camera.near) / (camera.far - camera.near);
+        corner_rays
+            .iter()
+            .map(move |(near, far)| near + (far - near) * fraction)
+    };
+
+    let near = camera.near.max(0.001);
+    let far = settings.distance.min(camera.far).max(near);
+    let count = settings.cascades as usize;
+    let mut matrices = Vec::with_capacity(count);
+    let mut splits = [0f32; 4];
+    let mut start = near;
+    for (index, split) in splits.iter_mut().enumerate().take(count) {
+        // halfway between even and logarithmic splits, so the near cascades stay small
+        let fraction = (index + 1) as f32 / count as f32;
+        let end = 0.5 * (near + (far - near) * fraction) + 0.5 * near * (far / near).powf(fraction);
+        *split = end;
+
+        let corners: Vec<Vec3> = corners_at(start).chain(corners_at(end)).collect();
+        let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|corner| nalgebra_glm::distance(corner, &center))
+            .fold(0f32, f32::max);
+
+        // the center moves in whole texels, so the shadows don't shimmer as the camera moves
+        let rotation = nalgebra_glm::look_at_rh(&nalgebra_glm::zero(), &direction, &up);
+        let texel = radius * 2.0 / settings.resolution as f32;
+        let mut light_center = rotation * nalgebra_glm::vec4(center.x, center.y, center.z, 1.0);
+        light_center.x = (light_center.x / texel).floor() * texel;
+        light_center.y = (light_center.y / texel).floor() * texel;
+        let snapped = nalgebra_glm::inverse(&rotation) * light_center;
+        let center = nalgebra_glm::vec3(snapped.x, snapped.y, snapped.z);
+
+        // objects up to the shadow distance in front of the cascade still cast into it
+        let eye = center - direction * (radius + settings.distance);
+        let view = nalgebra_glm::look_at_rh(&eye, &center, &up);
+        let projection = nalgebra_glm::ortho_rh_zo(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            radius * 2.0 + settings.distance,
+        );
+        matrices.push(projection * view);
+        start = end;
+    }
+
+    (matrices, splits)
+}
+
+/// A projection for each side of a cube around the light, in the order +X, -X, +Y, -Y, +Z, -Z
+fn point_matrices(position: Vec3, settings: &ShadowSettings) -> Vec<Mat4> {
+    let projection = nalgebra_glm::perspective_rh_zo(
+        1.0,
+        std::f32::consts::FRAC_PI_2,
+        SHADOW_NEAR,
+        settings.distance,
+    );
+    [
+        nalgebra_glm::vec3(1.0, 0.0, 0.0),
+        nalgebra_glm::vec3(-1.0, 0.0, 0.0),
+        nalgebra_glm::vec3(0.0, 1.0, 0.0),
+        nalgebra_glm::vec3(0.0, -1.0, 0.0),
+        nalgebra_glm::vec3(0.0, 0.0, 1.0),
+        nalgebra_glm::vec3(0.0, 0.0, -1.0),
+    ]
+    .iter()
+    .map(|direction| {
+        projection
+            * nalgebra_glm::look_at_rh(&position, &(position + direction), &up_for(direction))
+    })
+    .collect()
+}
+
+/// A projection covering the cone of a spot light
+fn spot_matrix(position: Vec3, direction: Vec3, angle: f32, settings: &ShadowSettings) -> Mat4 {
+    let fov = (angle * 2.0).clamp(0.01, std::f32::consts::PI - 0.01);
+    let projection = nalgebra_glm::perspective_rh_zo(1.0, fov, SHADOW_NEAR, settings.distance);
+
+    projection * nalgebra_glm::look_at_rh(&position, &(position + direction), &up_for(&direction))
+}
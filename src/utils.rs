@@ -7,6 +7,7 @@
 pub mod camera;
 pub mod default_resources;
 pub mod flycamera;
+pub mod golden;
 pub mod light;
 
 #[cfg(feature = "model_loader")]
@@ -13,9 +13,9 @@ use super::default_resources::DEFAULT_MATRIX_4;
 impl Camera {
     /// Creates a new camera. this should've been automatically done at the time of creating an engine
     pub fn new(window_size: PhysicalSize<u32>, renderer: &mut Renderer) -> Result<Self> {
-        let camera_uniform = renderer.build_uniform_buffer(&vec![
-            renderer.build_uniform_buffer_part("Camera Uniform", DEFAULT_MATRIX_4)
-        ])?;
+        let mut uniform_buffers =
+            vec![renderer.build_uniform_buffer_part("Camera Uniform", DEFAULT_MATRIX_4)];
+        let camera_uniform = renderer.build_uniform_buffer(&uniform_buffers)?;
 
         let mut camera = Self {
             position: nalgebra_glm::vec3(0.0, 0.0, 3.0),
@@ -28,6 +28,7 @@ impl Camera {
             view_data: DEFAULT_MATRIX_4.to_im(),
             changed: true,
             uniform_data: camera_uniform.0,
+            uniform_buffer: uniform_buffers.remove(0),
             add_position_and_target: false,
         };
         camera.build_view_projection_matrix()?;
@@ -152,15 +153,11 @@ impl Camera {
     /// This builds a uniform buffer data from camera view data that is sent to the GPU in next frame
     pub fn update_view_projection(&mut self, renderer: &mut Renderer) -> Result<()> {
         if self.changed {
-            let updated_buffer = renderer
-                .build_uniform_buffer(&vec![renderer.build_uniform_buffer_part(
-                    "Camera Uniform",
-                    self.camera_uniform_buffer()
-                        .expect("Couldn't build camera projection"),
-                )])
-                .expect("Couldn't update the camera uniform buffer")
-                .0;
-            self.uniform_data = updated_buffer;
+            renderer.queue.write_buffer(
+                &self.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[self.camera_uniform_buffer()?]),
+            );
             self.changed = false;
         }
 
@@ -26,16 +26,33 @@ struct VertexInput {
     @location(1) texture_coordinates: vec2<f32>,
 };
 
+struct InstanceInput {
+    @location(3) transform_matrix_0: vec4<f32>,
+    @location(4) transform_matrix_1: vec4<f32>,
+    @location(5) transform_matrix_2: vec4<f32>,
+    @location(6) transform_matrix_3: vec4<f32>,
+    @location(7) color: vec4<f32>,
+};
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) texture_coordinates: vec2<f32>,
+    @location(1) instance_color: vec4<f32>,
 };
 
 @vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
+fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let instance_matrix = mat4x4<f32>(
+        instance.transform_matrix_0,
+        instance.transform_matrix_1,
+        instance.transform_matrix_2,
+        instance.transform_matrix_3,
+    );
+
     var out: VertexOutput;
-    out.position = camera_uniform.camera_matrix * (transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0));
+    out.position = camera_uniform.camera_matrix * (transform_uniform.transform_matrix * (instance_matrix * vec4<f32>(input.position, 1.0)));
     out.texture_coordinates = input.texture_coordinates;
+    out.instance_color = instance.color;
     return out;
 }
 
@@ -56,7 +73,457 @@ var sampler_diffuse: sampler;
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * fragment_uniforms.color;
+    return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * fragment_uniforms.color * input.instance_color;
+}
+"#;
+
+pub const BACKGROUND_SHADER: &str = r#"
+struct BackgroundUniforms {
+    top_color: vec4<f32>,
+    bottom_color: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> background_uniforms: BackgroundUniforms;
+
+@group(0) @binding(0)
+var texture_diffuse: texture_2d<f32>;
+
+@group(0) @binding(1)
+var sampler_diffuse: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texture_coordinates: vec2<f32>,
+};
+
+// a single triangle that covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    out.texture_coordinates = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let gradient = mix(background_uniforms.top_color, background_uniforms.bottom_color, input.texture_coordinates.y);
+    return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * gradient;
+}
+"#;
+
+pub const SKYBOX_SHADER: &str = r#"
+struct SkyboxUniforms {
+    inverse_view_projection: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> skybox_uniforms: SkyboxUniforms;
+
+@group(0) @binding(0)
+var skybox_texture: texture_cube<f32>;
+
+@group(0) @binding(1)
+var skybox_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) screen_position: vec2<f32>,
+};
+
+// a single triangle that covers the whole screen, at the far plane
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    out.screen_position = uv * 2.0 - 1.0;
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let world = skybox_uniforms.inverse_view_projection * vec4<f32>(input.screen_position, 1.0, 1.0);
+    return textureSample(skybox_texture, skybox_sampler, world.xyz / world.w);
+}
+"#;
+
+/// Shared by the post effects, followed by the fragment stage of each effect
+pub const POST_EFFECT_SHADER: &str = r#"
+struct EffectUniforms {
+    values: array<vec4<f32>, 2>,
+};
+@group(1) @binding(0)
+var<uniform> effect: EffectUniforms;
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texture_coordinates: vec2<f32>,
+};
+
+// a single triangle that covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.texture_coordinates = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+"#;
+
+/// Adds the blurred glow from group 2 to the frame
+pub const BLOOM_SHADER: &str = r#"
+@group(2) @binding(0)
+var glow_texture: texture_2d<f32>;
+
+@group(2) @binding(1)
+var glow_sampler: sampler;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let intensity = effect.values[0].y;
+    let color = textureSampleLevel(input_texture, input_sampler, input.texture_coordinates, 0.0);
+    let glow = textureSampleLevel(glow_texture, glow_sampler, input.texture_coordinates, 0.0).rgb;
+
+    return vec4<f32>(color.rgb + glow * intensity, color.a);
+}
+"#;
+
+/// A gaussian blur along one axis, drawn at half resolution. Every texel within the radius
+/// is taken, so that large radii don't skip over any. Needs `blur_step` and `bright_part`
+pub const BLOOM_BLUR_SHADER: &str = r#"
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let threshold = effect.values[0].x;
+    let texel_step = blur_step() / vec2<f32>(textureDimensions(input_texture));
+    // the radius is in pixels of the frame, which are half a texel here
+    let taps = i32(min(ceil(effect.values[0].z / 2.0), 64.0));
+    let sigma = max(f32(taps) / 2.5, 0.5);
+
+    var glow = vec3<f32>(0.0, 0.0, 0.0);
+    var total = 0.0;
+    for (var i = -taps; i <= taps; i = i + 1) {
+        let weight = exp(-0.5 * f32(i * i) / (sigma * sigma));
+        let tap = textureSampleLevel(input_texture, input_sampler, input.texture_coordinates + f32(i) * texel_step, 0.0).rgb;
+        glow = glow + bright_part(tap, threshold) * weight;
+        total = total + weight;
+    }
+
+    return vec4<f32>(glow / total, 1.0);
+}
+"#;
+
+/// The first bloom blur, horizontal, keeping the parts brighter than the threshold. It reads
+/// the frame at full resolution, two texels at a time
+pub const BLOOM_BLUR_X_SHADER: &str = r#"
+fn blur_step() -> vec2<f32> {
+    return vec2<f32>(2.0, 0.0);
+}
+
+fn bright_part(color: vec3<f32>, threshold: f32) -> vec3<f32> {
+    let brightness = luminance(color);
+    return color * max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+}
+"#;
+
+/// The second bloom blur, vertical, over the result of the first one
+pub const BLOOM_BLUR_Y_SHADER: &str = r#"
+fn blur_step() -> vec2<f32> {
+    return vec2<f32>(0.0, 1.0);
+}
+
+fn bright_part(color: vec3<f32>, threshold: f32) -> vec3<f32> {
+    return color;
+}
+"#;
+
+pub const TONEMAPPING_SHADER: &str = r#"
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let mapped = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, input.texture_coordinates, 0.0);
+    let exposed = color.rgb * effect.values[0].y;
+
+    if (effect.values[0].x < 0.5) {
+        return vec4<f32>(exposed / (exposed + 1.0), color.a);
+    }
+    return vec4<f32>(aces(exposed), color.a);
+}
+"#;
+
+pub const FXAA_SHADER: &str = r#"
+fn sample_luminance(position: vec2<f32>) -> f32 {
+    return luminance(textureSampleLevel(input_texture, input_sampler, position, 0.0).rgb);
+}
+
+fn sample_color(position: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(input_texture, input_sampler, position, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+    let position = input.texture_coordinates;
+    let center = textureSampleLevel(input_texture, input_sampler, position, 0.0);
+
+    let luminance_center = luminance(center.rgb);
+    let luminance_nw = sample_luminance(position + vec2<f32>(-1.0, -1.0) * texel);
+    let luminance_ne = sample_luminance(position + vec2<f32>(1.0, -1.0) * texel);
+    let luminance_sw = sample_luminance(position + vec2<f32>(-1.0, 1.0) * texel);
+    let luminance_se = sample_luminance(position + vec2<f32>(1.0, 1.0) * texel);
+
+    let luminance_min = min(luminance_center, min(min(luminance_nw, luminance_ne), min(luminance_sw, luminance_se)));
+    let luminance_max = max(luminance_center, max(max(luminance_nw, luminance_ne), max(luminance_sw, luminance_se)));
+    if (luminance_max - luminance_min < max(effect.values[0].y, luminance_max * effect.values[0].x)) {
+        return center;
+    }
+
+    // blur along the edge, which runs across the direction of the largest change
+    var direction = vec2<f32>(
+        (luminance_sw + luminance_se) - (luminance_nw + luminance_ne),
+        (luminance_nw + luminance_sw) - (luminance_ne + luminance_se),
+    );
+    let reduce = max((luminance_nw + luminance_ne + luminance_sw + luminance_se) * 0.03125, 0.0078125);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-8.0, -8.0), vec2<f32>(8.0, 8.0)) * texel;
+
+    let near = 0.5 * (sample_color(position - direction / 6.0) + sample_color(position + direction / 6.0));
+    let far = near * 0.5 + 0.25 * (sample_color(position - direction * 0.5) + sample_color(position + direction * 0.5));
+
+    // the wider blur is only used if it didn't pick up colors from across another edge
+    let luminance_far = luminance(far);
+    if (luminance_far < luminance_min || luminance_far > luminance_max) {
+        return vec4<f32>(near, center.a);
+    }
+    return vec4<f32>(far, center.a);
+}
+"#;
+
+pub const VIGNETTE_SHADER: &str = r#"
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, input.texture_coordinates, 0.0);
+    let intensity = effect.values[0].x;
+    let radius = effect.values[0].y;
+    let smoothness = max(effect.values[0].z, 0.0001);
+
+    // 0 at the center and 1 at the corners
+    let distance = length(input.texture_coordinates - 0.5) * 1.41421356;
+    let darkening = smoothstep(radius, radius + smoothness, distance) * intensity;
+
+    return vec4<f32>(color.rgb * (1.0 - darkening), color.a);
+}
+"#;
+
+pub const COLOR_GRADING_SHADER: &str = r#"
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(input_texture, input_sampler, input.texture_coordinates, 0.0);
+    let brightness = effect.values[0].x;
+    let contrast = effect.values[0].y;
+    let saturation = effect.values[0].z;
+
+    var graded = color.rgb * effect.values[1].rgb + brightness;
+    // the colors are linear, where middle gray is 0.18
+    graded = (graded - 0.18) * contrast + 0.18;
+    let gray = luminance(graded);
+    graded = mix(vec3<f32>(gray, gray, gray), graded, saturation);
+
+    return vec4<f32>(max(graded, vec3<f32>(0.0, 0.0, 0.0)), color.a);
+}
+"#;
+
+// the vertex and instance input are added for the vertex layout of the caster
+pub const SHADOW_CASTER_SHADER: &str = r#"
+struct ShadowCasterUniforms {
+    light_matrix: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> shadow_caster: ShadowCasterUniforms;
+
+struct TransformationUniforms {
+    transform_matrix: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> transform_uniform: TransformationUniforms;
+"#;
+
+// sampled by shaders with `ShaderSettings::lighting`, through `shadow_factor`. Needs the
+// `ShadowUniforms` declared by `shadows::shadow_uniforms_shader` before it
+pub const SHADOW_SHADER: &str = r#"
+@group(3) @binding(0)
+var shadow_map: texture_depth_2d_array;
+@group(3) @binding(1)
+var shadow_sampler: sampler_comparison;
+@group(3) @binding(2)
+var<uniform> shadow_uniforms: ShadowUniforms;
+
+// the layer of the caster covering the position, counted from the first layer of the caster
+fn shadow_layer(caster: i32, world_position: vec3<f32>) -> i32 {
+    let kind = shadow_uniforms.casters[caster].light.w;
+    if (kind < 0.5) {
+        // directional lights use the cascade covering the distance from the camera
+        let depth = dot(world_position - shadow_uniforms.camera_position.xyz, shadow_uniforms.camera_forward.xyz);
+        var layer = 0;
+        for (var i = 0; i < i32(shadow_uniforms.casters[caster].layers.y) - 1; i = i + 1) {
+            if (depth > shadow_uniforms.casters[caster].cascade_splits[i]) {
+                layer = i + 1;
+            }
+        }
+        return layer;
+    }
+    if (kind < 1.5) {
+        // point lights use the face of the cube the fragment is in
+        let to_fragment = world_position - shadow_uniforms.casters[caster].light.xyz;
+        let distance = abs(to_fragment);
+        if (distance.x >= distance.y && distance.x >= distance.z) {
+            return select(1, 0, to_fragment.x > 0.0);
+        }
+        if (distance.y >= distance.z) {
+            return select(3, 2, to_fragment.y > 0.0);
+        }
+        return select(5, 4, to_fragment.z > 0.0);
+    }
+    return 0;
+}
+
+// 0 when the fragment is fully in the shadow of the caster, 1 when it's fully lit
+fn shadow_factor(caster: i32, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let settings = shadow_uniforms.casters[caster].settings;
+    let layer = i32(shadow_uniforms.casters[caster].layers.x) + shadow_layer(caster, world_position);
+    let offset_position = world_position + normalize(normal) * settings.y;
+    let position = shadow_uniforms.matrices[layer] * vec4<f32>(offset_position, 1.0);
+    if (position.w <= 0.0) {
+        return 1.0;
+    }
+    let coordinates = position.xyz / position.w;
+    let uv = vec2<f32>(coordinates.x * 0.5 + 0.5, 0.5 - coordinates.y * 0.5);
+    if (coordinates.z > 1.0 || uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        return 1.0;
+    }
+
+    let depth = coordinates.z - settings.x;
+    let radius = i32(settings.z);
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var x = -radius; x <= radius; x = x + 1) {
+        for (var y = -radius; y <= radius; y = y + 1) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, layer, depth);
+        }
+    }
+    let size = f32(radius * 2 + 1);
+    return lit / (size * size);
+}
+"#;
+
+// the lights of a `LightManager`, for shaders with `ShaderSettings::lighting`. Needs the
+// `Lights` declared by `light::lights_uniforms_shader` before it
+pub const LIGHTS_SHADER: &str = r#"
+@group(3) @binding(3)
+var<uniform> lights: Lights;
+
+// how much of the light reaches the fragment, and the direction towards the light
+fn light_attenuation(light: Light, world_position: vec3<f32>) -> vec4<f32> {
+    if (light.position.w < 0.5) {
+        return vec4<f32>(-normalize(light.direction.xyz), 1.0);
+    }
+
+    let to_light = light.position.xyz - world_position;
+    let distance = max(length(to_light), 0.0001);
+    let light_dir = to_light / distance;
+    // inverse square falloff, windowed to reach zero at the range
+    let window = clamp(1.0 - pow(distance / light.color.w, 4.0), 0.0, 1.0);
+    var attenuation = window * window / max(distance * distance, 0.01);
+    if (light.position.w > 1.5) {
+        // spot lights fade out at the edge of their cone
+        let theta = dot(-light_dir, normalize(light.direction.xyz));
+        let cutoff = light.direction.w;
+        attenuation = attenuation * smoothstep(cutoff, cutoff + (1.0 - cutoff) * 0.1, theta);
+    }
+    return vec4<f32>(light_dir, attenuation);
+}
+"#;
+
+// the maps and factors of a `Material`, and the metallic-roughness BRDF of glTF 2.0.
+// The base color and sampler are `texture_diffuse` and `sampler_diffuse`
+pub const MATERIAL_SHADER: &str = r#"
+struct MaterialUniforms {
+    base_color: vec4<f32>,
+    // emissive color, and normal scale
+    emissive: vec4<f32>,
+    // metallic, roughness and occlusion strength
+    factors: vec4<f32>,
+};
+@group(0) @binding(2)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(0) @binding(4)
+var occlusion_texture: texture_2d<f32>;
+@group(0) @binding(5)
+var emissive_texture: texture_2d<f32>;
+@group(0) @binding(6)
+var<uniform> material: MaterialUniforms;
+
+let PI: f32 = 3.14159265359;
+
+// the normal of the normal map, in a tangent frame derived from the screen space
+// derivatives of the position and texture coordinates
+fn material_normal(normal: vec3<f32>, world_position: vec3<f32>, uv: vec2<f32>, map: vec3<f32>) -> vec3<f32> {
+    let ng = normalize(normal);
+    let uv_dx = dpdx(uv);
+    let uv_dy = dpdy(uv);
+    let determinant = uv_dx.x * uv_dy.y - uv_dy.x * uv_dx.y;
+    let t = (uv_dy.y * dpdx(world_position) - uv_dx.y * dpdy(world_position)) * sign(determinant);
+    let projected = t - ng * dot(ng, t);
+    if (dot(projected, projected) < 0.00000001) {
+        return ng;
+    }
+    let tangent = normalize(projected);
+    let bitangent = cross(ng, tangent);
+    let n = (map * 2.0 - 1.0) * vec3<f32>(material.emissive.w, material.emissive.w, 1.0);
+    return normalize(mat3x3<f32>(tangent, bitangent, ng) * n);
+}
+
+// the light reflected towards the viewer, for light of intensity 1 from the direction of the light
+fn brdf(base_color: vec3<f32>, metallic: f32, roughness: f32, n: vec3<f32>, v: vec3<f32>, l: vec3<f32>) -> vec3<f32> {
+    let h = normalize(l + v);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let v_dot_h = max(dot(v, h), 0.0);
+    let alpha = roughness * roughness;
+    let alpha_squared = alpha * alpha;
+
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    // GGX distribution and height correlated Smith visibility
+    let d = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    let distribution = alpha_squared / (PI * d * d);
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha_squared) + alpha_squared);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha_squared) + alpha_squared);
+    let visibility = 0.5 / max(ggx_v + ggx_l, 0.0001);
+
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color / PI;
+    let specular = fresnel * distribution * visibility;
+    return (diffuse + specular) * n_dot_l;
 }
 "#;
 
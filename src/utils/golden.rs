@@ -0,0 +1,196 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use crate::{Engine, LightManager, PowerPreference};
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Set this environment variable to overwrite the stored references with the new renders
+pub const UPDATE_GOLDEN_ENV: &str = "BLUE_ENGINE_UPDATE_GOLDEN";
+
+/// Renders scenes offscreen and compares them against reference images stored on disk.
+/// Meant to be used from tests, to catch changes that silently break the visuals.
+///
+/// ```no_run
+/// use blue_engine::{primitive_shapes::triangle, utils::golden::GoldenTest, ObjectSettings};
+///
+/// let golden = GoldenTest::new("tests/golden");
+/// let frame = golden
+///     .render_scene(
+///         |engine| triangle("triangle", ObjectSettings::default(), engine),
+///         None,
+///     )
+///     .unwrap();
+/// golden.check("triangle", &frame).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct GoldenTest {
+    /// The folder where reference images are stored as `<name>.png`
+    pub reference_dir: PathBuf,
+    /// Width of the rendered frame
+    pub width: u32,
+    /// Height of the rendered frame
+    pub height: u32,
+    /// The largest difference allowed on each channel of a pixel before it counts as mismatched
+    pub tolerance: u8,
+    /// How many pixels are allowed to mismatch before the check fails
+    pub max_mismatched_pixels: usize,
+    /// Render on a software adapter, for machines without a GPU
+    pub force_fallback_adapter: bool,
+    /// The number of samples per pixel used for MSAA
+    pub sample_count: u32,
+}
+
+/// The result of comparing two images
+#[derive(Debug, Clone)]
+pub struct ImageComparison {
+    /// Amount of pixels that differ more than the tolerance
+    pub mismatched_pixels: usize,
+    /// The largest difference found on a single channel
+    pub max_difference: u8,
+    /// Mismatched pixels are shown in red, the rest is a faded copy of the expected image
+    pub diff: RgbaImage,
+}
+
+impl GoldenTest {
+    /// Creates a new golden test with 256x256 frames and a small tolerance
+    pub fn new<P: AsRef<Path>>(reference_dir: P) -> Self {
+        Self {
+            reference_dir: reference_dir.as_ref().to_path_buf(),
+            width: 256,
+            height: 256,
+            tolerance: 2,
+            max_mismatched_pixels: 0,
+            force_fallback_adapter: true,
+            sample_count: 1,
+        }
+    }
+
+    /// Creates a headless engine with the settings of this test
+    pub fn engine(&self) -> anyhow::Result<Engine> {
+        let mut engine = Engine::new_headless(
+            self.width,
+            self.height,
+            PowerPreference::LowPower,
+            self.force_fallback_adapter,
+        )?;
+        if self.sample_count != 1 {
+            engine
+                .renderer
+                .set_sample_count(self.sample_count, &mut engine.objects)?;
+        }
+
+        Ok(engine)
+    }
+
+    /// Creates a headless engine, lets the `setup` fill the scene and modify the camera,
+    /// and returns the rendered frame. The light manager, if given, is applied before rendering.
+    pub fn render_scene<F: FnOnce(&mut Engine) -> anyhow::Result<()>>(
+        &self,
+        setup: F,
+        light_manager: Option<&mut LightManager>,
+    ) -> anyhow::Result<RgbaImage> {
+        let mut engine = self.engine()?;
+        setup(&mut engine)?;
+
+        if let Some(light_manager) = light_manager {
+            light_manager.update(&mut engine.objects, &mut engine.renderer, &engine.camera)?;
+        }
+
+        engine.render_frame()?;
+        engine.renderer.capture_frame()
+    }
+
+    /// Compares the image with the reference stored under the given name.
+    ///
+    /// When the `BLUE_ENGINE_UPDATE_GOLDEN` environment variable is set, the image is stored
+    /// as the new reference instead. A missing reference fails the check. On failure the render
+    /// and a diff image are written next to the reference as `<name>.actual.png` and `<name>.diff.png`.
+    pub fn check(&self, name: &str, image: &RgbaImage) -> anyhow::Result<()> {
+        let reference_path = self.reference_dir.join(format!("{}.png", name));
+
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            std::fs::create_dir_all(&self.reference_dir)?;
+            image.save(&reference_path)?;
+            log::info!("Stored new golden image at {}", reference_path.display());
+            return Ok(());
+        }
+        if !reference_path.exists() {
+            std::fs::create_dir_all(&self.reference_dir)?;
+            image.save(self.reference_dir.join(format!("{}.actual.png", name)))?;
+            anyhow::bail!(
+                "missing golden image {}; rerun with {}=1",
+                name,
+                UPDATE_GOLDEN_ENV
+            );
+        }
+
+        let expected = image::open(&reference_path)?.to_rgba8();
+        if expected.dimensions() != image.dimensions() {
+            image.save(self.reference_dir.join(format!("{}.actual.png", name)))?;
+            anyhow::bail!(
+                "Golden image {} is {:?} but the render is {:?}",
+                name,
+                expected.dimensions(),
+                image.dimensions()
+            );
+        }
+
+        let comparison = compare_images(image, &expected, self.tolerance);
+        if comparison.mismatched_pixels > self.max_mismatched_pixels {
+            image.save(self.reference_dir.join(format!("{}.actual.png", name)))?;
+            comparison
+                .diff
+                .save(self.reference_dir.join(format!("{}.diff.png", name)))?;
+            anyhow::bail!(
+                "Golden image {} has {} mismatched pixels (max difference {}), see {}.diff.png",
+                name,
+                comparison.mismatched_pixels,
+                comparison.max_difference,
+                name
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares two images of the same size pixel by pixel
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> ImageComparison {
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut mismatched_pixels = 0usize;
+    let mut max_difference = 0u8;
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let difference = expected_pixel
+            .0
+            .iter()
+            .zip(actual_pixel.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched_pixels += 1;
+            diff.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        } else {
+            let luma = (expected_pixel.0[0] as u32
+                + expected_pixel.0[1] as u32
+                + expected_pixel.0[2] as u32)
+                / 3;
+            let faded = (luma / 4) as u8;
+            diff.put_pixel(x, y, Rgba([faded, faded, faded, 255]));
+        }
+    }
+
+    ImageComparison {
+        mismatched_pixels,
+        max_difference,
+        diff,
+    }
+}
@@ -1,14 +1,95 @@
-use crate::Object;
+use crate::{
+    objects::{instance_input, position_expression, texture_coordinates_expression, vertex_input},
+    shadows::{shadow_uniforms_shader, MAX_SHADOW_CASTERS},
+    utils::default_resources::{LIGHTS_SHADER, MATERIAL_SHADER, SHADOW_SHADER},
+    Light, LightKind, Object,
+};
+
+/// The most lights that can shine at once, unless `LightManager::max_lights` is changed
+pub const DEFAULT_MAX_LIGHTS: usize = 16;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
 pub struct LightUniforms {
-    light_color: crate::uniform_type::Array4,     // 4 units
-    light_position: crate::uniform_type::Array3,  // 3 units
-    ambient_strength: f32,                        // 1 unit
     camera_position: crate::uniform_type::Array3, // 3 units
-    specular_strength: f32,                       // 1 unit
+    ambient_strength: f32,                        // 1 unit
     inverse_model: crate::uniform_type::Matrix,   // 4x4 units
+    specular_strength: f32,                       // 1 unit
+    receive_shadows: f32,                         // 1 unit
+    padding: [f32; 2],                            // 2 units
+}
+
+/// A light as it's laid out in `lights_uniforms_shader`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+pub(crate) struct LightData {
+    position: [f32; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+    shadow: [f32; 4],
+}
+
+/// Size of the buffer of the lights shared by every lit object, with room for the given amount
+/// of lights followed by the amount that shine
+pub(crate) fn light_buffer_size(max_lights: usize) -> wgpu::BufferAddress {
+    (std::mem::size_of::<LightData>() * max_lights + std::mem::size_of::<[f32; 4]>())
+        as wgpu::BufferAddress
+}
+
+/// Declares the lights of `LIGHTS_SHADER`, with room for the given amount of them
+pub(crate) fn lights_uniforms_shader(max_lights: usize) -> String {
+    format!(
+        r#"
+struct Light {{
+    // position and kind, 0 for directional, 1 for point and 2 for spot lights
+    position: vec4<f32>,
+    // direction and the cosine of the spot angle
+    direction: vec4<f32>,
+    // color multiplied by the intensity, and range
+    color: vec4<f32>,
+    // index of the shadow caster of the light, or -1 when it casts no shadows
+    shadow: vec4<f32>,
+}};
+struct Lights {{
+    lights: array<Light, {}>,
+    // amount of lights
+    count: vec4<f32>,
+}};"#,
+        max_lights
+    )
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Point,
+            color: [1f32, 1f32, 1f32],
+            intensity: 1f32,
+            range: 20f32,
+            shadows: None,
+        }
+    }
+}
+
+impl LightData {
+    fn new(light: &Light, position: [f32; 3], caster: Option<usize>) -> Self {
+        let (kind, direction, cutoff) = match light.kind {
+            LightKind::Directional { direction } => (0f32, direction, 0f32),
+            LightKind::Point => (1f32, [0f32; 3], 0f32),
+            LightKind::Spot { direction, angle } => (2f32, direction, angle.cos()),
+        };
+        Self {
+            position: [position[0], position[1], position[2], kind],
+            direction: [direction[0], direction[1], direction[2], cutoff],
+            color: [
+                light.color[0] * light.intensity,
+                light.color[1] * light.intensity,
+                light.color[2] * light.intensity,
+                light.range,
+            ],
+            shadow: [caster.map_or(-1f32, |i| i as f32), 0f32, 0f32, 0f32],
+        }
+    }
 }
 
 impl crate::LightManager {
@@ -18,8 +99,10 @@ impl crate::LightManager {
                 data: [1f32, 1f32, 1f32, 1f32], //0.051f32, 0.533f32, 0.898f32
             },
             ambient_strength: 0f32,
+            max_lights: DEFAULT_MAX_LIGHTS,
             affected_objects: Vec::new(),
             light_objects: std::collections::BTreeMap::new(),
+            lights: std::collections::BTreeMap::new(),
         }
     }
 
@@ -31,14 +114,82 @@ impl crate::LightManager {
     ) -> anyhow::Result<()> {
         let light_keys: Vec<&'static str> = self.light_objects.keys().map(|x| *x).collect();
 
-        for i in objects {
-            let i = i.1;
+        // lights are gathered first, so every object sees this frame's light data
+        for i in objects.values() {
             if light_keys.contains(&i.name) {
                 self.light_objects.insert(
                     i.name,
                     ([i.position.0, i.position.1, i.position.2], i.color),
                 );
-            } else {
+            }
+        }
+
+        if light_keys.len() > self.max_lights {
+            anyhow::bail!(
+                "At most {} lights can shine at once, got {}",
+                self.max_lights,
+                light_keys.len()
+            );
+        }
+        // the shaders of the lit objects are rebuilt for a new amount of lights
+        let resized = renderer.resize_light_buffer(self.max_lights)?;
+        let lights: Vec<(Light, [f32; 3])> = light_keys
+            .iter()
+            .map(|name| {
+                let position = self.light_objects[name].0;
+                (
+                    self.lights.get(name).copied().unwrap_or_default(),
+                    [-position[0], -position[1], -position[2]],
+                )
+            })
+            .collect();
+        // each light with shadows gets its own shadow maps, in the order of their names
+        let mut casters = Vec::new();
+        let mut ignored_casters = Vec::new();
+        let mut light_data = vec![<LightData as bytemuck::Zeroable>::zeroed(); self.max_lights];
+        for (index, (data, (light, position))) in
+            light_data.iter_mut().zip(lights.iter()).enumerate()
+        {
+            let mut caster = None;
+            if light.shadows.is_some() {
+                if casters.len() < MAX_SHADOW_CASTERS {
+                    caster = Some(casters.len());
+                    casters.push((*light, *position));
+                } else {
+                    ignored_casters.push(light_keys[index]);
+                }
+            }
+            *data = LightData::new(light, *position, caster);
+        }
+        // warned about once, rather than every frame
+        if !ignored_casters.is_empty() && ignored_casters != renderer.shadow_maps.ignored_casters {
+            log::warn!(
+                "At most {} lights can cast shadows at once, so {} don't",
+                MAX_SHADOW_CASTERS,
+                ignored_casters.join(", ")
+            );
+        }
+        renderer.shadow_maps.ignored_casters = ignored_casters;
+        renderer.queue.write_buffer(
+            &renderer.shadow_maps.light_buffer,
+            0,
+            bytemuck::cast_slice(&light_data),
+        );
+        renderer.queue.write_buffer(
+            &renderer.shadow_maps.light_buffer,
+            (std::mem::size_of::<LightData>() * self.max_lights) as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[lights.len() as f32, 0f32, 0f32, 0f32]),
+        );
+
+        for i in objects.iter_mut() {
+            let i = i.1;
+            if !light_keys.contains(&i.name) {
+                if i.vertex_layout.format_of("normal") != Some(wgpu::VertexFormat::Float32x3) {
+                    anyhow::bail!(
+                        "The object {} can't be lit, its vertex layout needs a normal attribute of three floats",
+                        i.name
+                    );
+                }
                 let result = i.color * self.ambient_color;
                 i.set_uniform_color(
                     result.data[0],
@@ -47,34 +198,39 @@ impl crate::LightManager {
                     result.data[3],
                 )?;
 
-                let pos = *self.light_objects.get(&light_keys[0]).unwrap();
-                let light_uniform_buffer = renderer.build_uniform_buffer_part(
-                    "light_uniform_buffer",
-                    LightUniforms {
-                        light_color: pos.1,
-                        light_position: crate::uniform_type::Array3 {
-                            data: [pos.0[0] * -1f32, pos.0[1] * -1f32, pos.0[2] * -1f32],
-                        },
-                        ambient_strength: self.ambient_strength,
-                        inverse_model: i.inverse_transformation_matrix,
-                        camera_position: crate::uniform_type::Array3 {
-                            data: camera.position.data.0[0],
-                        },
-                        specular_strength: 0.8,
+                let light_uniforms = LightUniforms {
+                    ambient_strength: self.ambient_strength,
+                    inverse_model: i.inverse_transformation_matrix,
+                    camera_position: crate::uniform_type::Array3 {
+                        data: camera.position.data.0[0],
                     },
-                );
+                    specular_strength: 0.8,
+                    receive_shadows: if i.receive_shadows { 1f32 } else { 0f32 },
+                    padding: [0f32; 2],
+                };
+                // the buffer is added once, after that it's written in place
                 if i.uniform_buffers.len() == 2 {
-                    i.uniform_buffers.push(light_uniform_buffer);
+                    i.uniform_buffers.push(
+                        renderer.build_uniform_buffer_part("light_uniform_buffer", light_uniforms),
+                    );
+                    i.update_uniform_buffer(renderer)?;
                 } else {
-                    i.uniform_buffers[2] = light_uniform_buffer;
+                    renderer.queue.write_buffer(
+                        &i.uniform_buffers[2],
+                        0,
+                        bytemuck::cast_slice(&[light_uniforms]),
+                    );
                 }
 
-                i.update_uniform_buffer(renderer)?;
-
-                if !self.affected_objects.contains(&i.name) {
+                // the shader is rebuilt when a material is given to the object
+                let material = i.material.is_some();
+                if resized
+                    || !self.affected_objects.contains(&i.name)
+                    || i.shader_settings.material != material
+                {
                     i.shader_builder.blocks = format!(
                         // step 1 define blocks
-                        "\n{}\n{}\n{}",
+                        "\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
                         r#"
 struct TransformationUniforms {
     transform_matrix: mat4x4<f32>,
@@ -89,12 +245,11 @@ struct FragmentUniforms {
 var<uniform> fragment_uniforms: FragmentUniforms;
 
 struct LightUniforms {
-    light_color: vec4<f32>,
-    light_position: vec3<f32>,
-    ambient_strength: f32,
     camera_position: vec3<f32>,
-    specular_strength: f32,
+    ambient_strength: f32,
     inverse_model: mat4x4<f32>,
+    specular_strength: f32,
+    receive_shadows: f32,
 };
 @group(2) @binding(2)
 var<uniform> light_uniform_buffer: LightUniforms;"#,
@@ -107,16 +262,18 @@ struct CameraUniforms {
 var<uniform> camera_uniform: CameraUniforms;"#
                         } else {
                             ""
-                        }
+                        },
+                        shadow_uniforms_shader(),
+                        SHADOW_SHADER,
+                        lights_uniforms_shader(self.max_lights),
+                        LIGHTS_SHADER,
+                        if material { MATERIAL_SHADER } else { "" }
                     );
                     i.shader_builder.input_and_output = format!(
-                        "\n{}",
-                        /* wgsl */
-                        r#"struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) texture_coordinates: vec2<f32>,
-    @location(2) normal: vec3<f32>,
-};
+                        "\nstruct VertexInput {{\n{}\n}};\n\nstruct InstanceInput {{\n{}\n}};\n{}",
+                        vertex_input(&i.vertex_layout),
+                        instance_input(i.vertex_layout.next_location()),
+                        r#"
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
@@ -127,18 +284,25 @@ struct VertexOutput {
 };"#
                     );
                     i.shader_builder.vertex_stage = format!(
-                        "\n// ===== VERTEX STAGE ===== //\n{}\n{}\n{}",
+                        "\n// ===== VERTEX STAGE ===== //\n{}\nout.texture_coordinates = {};\nout.fragment_position = (transform_uniform.transform_matrix * (instance_matrix * {})).xyz;\n{}\n{}\n{}",
                         r#"@vertex
-fn vs_main(input: VertexInput) -> VertexOutput {
-    var out: VertexOutput;
-    out.texture_coordinates = input.texture_coordinates;
-    out.normal = (light_uniform_buffer.inverse_model * vec4<f32>(input.normal, 0.0)).xyz;
-    out.fragment_position = (transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0)).xyz;
-    out.ambient_intensity = light_uniform_buffer.ambient_strength;"#,
+fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let instance_matrix = mat4x4<f32>(
+        instance.transform_matrix_0,
+        instance.transform_matrix_1,
+        instance.transform_matrix_2,
+        instance.transform_matrix_3,
+    );
+
+    var out: VertexOutput;"#,
+                        texture_coordinates_expression(&i.vertex_layout),
+                        position_expression(&i.vertex_layout),
+                        r#"out.normal = (light_uniform_buffer.inverse_model * vec4<f32>(input.normal, 0.0)).xyz;
+out.ambient_intensity = light_uniform_buffer.ambient_strength;"#,
                         if i.camera_effect {
-                            "out.position = camera_uniform.camera_matrix * (transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0));"
+                            "out.position = camera_uniform.camera_matrix * vec4<f32>(out.fragment_position, 1.0);"
                         } else {
-                            "out.position = transform_uniform.transform_matrix * vec4<f32>(input.position, 1.0);"
+                            "out.position = vec4<f32>(out.fragment_position, 1.0);"
                         },
                         r#"return out;
 }"#
@@ -147,43 +311,100 @@ fn vs_main(input: VertexInput) -> VertexOutput {
                     i.shader_builder.fragment_stage = format!(
                         // step 5 fragment stage
                         "\n// ===== Fragment STAGE ===== //\n{}",
-                        r#"@fragment
+                        if material {
+                            r#"@fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    // ambient
-    var ambient: vec4<f32> = input.ambient_intensity * light_uniform_buffer.light_color;
+    let uv = input.texture_coordinates;
+    let base_color = material.base_color * textureSample(texture_diffuse, sampler_diffuse, uv) * fragment_uniforms.color;
+    let metallic_roughness = textureSample(metallic_roughness_texture, sampler_diffuse, uv);
+    let metallic = clamp(material.factors.x * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(material.factors.y * metallic_roughness.g, 0.04, 1.0);
+    let occlusion = 1.0 + material.factors.z * (textureSample(occlusion_texture, sampler_diffuse, uv).r - 1.0);
+    let emissive = material.emissive.rgb * textureSample(emissive_texture, sampler_diffuse, uv).rgb;
+    let normal_sample = textureSample(normal_texture, sampler_diffuse, uv).xyz;
 
-    // diffuse
-    var norm: vec3<f32> = normalize(input.normal);
-    var light_dir: vec3<f32> = normalize(light_uniform_buffer.light_position - input.fragment_position);
-    var diff: f32 = max(dot(norm, light_dir), 0.0);
-    var diffuse = diff * light_uniform_buffer.light_color;
+    let norm = material_normal(input.normal, input.fragment_position, uv, normal_sample);
+    let view_dir = normalize(light_uniform_buffer.camera_position - input.fragment_position);
+    var color = input.ambient_intensity * base_color.rgb * occlusion;
 
-    // specular
+    for (var i = 0; i < i32(lights.count.x); i = i + 1) {
+        let light = lights.lights[i];
+        let attenuation = light_attenuation(light, input.fragment_position);
+        var intensity = attenuation.w;
+        if (light.shadow.x > -0.5 && light_uniform_buffer.receive_shadows > 0.5) {
+            intensity = intensity * shadow_factor(i32(light.shadow.x), input.fragment_position, norm);
+        }
+
+        color = color + brdf(base_color.rgb, metallic, roughness, norm, view_dir, attenuation.xyz) * light.color.rgb * intensity;
+    }
+
+    return vec4<f32>(color + emissive, base_color.a);
+}"#
+                        } else {
+                            r#"@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var norm: vec3<f32> = normalize(input.normal);
     var view_dir: vec3<f32> = normalize(light_uniform_buffer.camera_position - input.fragment_position);
-    var reflect_dir: vec3<f32> = reflect(-light_dir, norm);
-    var spec: f32 = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
-    var specular = light_uniform_buffer.specular_strength * spec * light_uniform_buffer.light_color;
+    var lighting = vec3<f32>(input.ambient_intensity);
+
+    for (var i = 0; i < i32(lights.count.x); i = i + 1) {
+        let light = lights.lights[i];
+        let attenuation = light_attenuation(light, input.fragment_position);
+        let light_dir = attenuation.xyz;
+        var intensity = attenuation.w;
+        if (light.shadow.x > -0.5 && light_uniform_buffer.receive_shadows > 0.5) {
+            intensity = intensity * shadow_factor(i32(light.shadow.x), input.fragment_position, norm);
+        }
+
+        // diffuse
+        let diff = max(dot(norm, light_dir), 0.0);
+
+        // specular
+        let reflect_dir = reflect(-light_dir, norm);
+        let spec = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0) * light_uniform_buffer.specular_strength;
+
+        lighting = lighting + intensity * (diff + spec) * light.color.rgb;
+    }
 
-    var result = (ambient + diffuse + specular) * fragment_uniforms.color;
+    var result = vec4<f32>(lighting, 1.0) * fragment_uniforms.color;
 
     return textureSample(texture_diffuse, sampler_diffuse, input.texture_coordinates) * result;
 }"#
+                        }
                     );
-                    i.pipeline.shader = renderer.build_shader(
+                    i.shader_settings.lighting = true;
+                    i.shader_settings.material = material;
+                    i.pipeline.shader = renderer.build_cached_shader(
                         i.name,
                         i.shader_builder.build_shader(),
                         Some(&i.uniform_layout),
-                        i.shader_settings,
+                        i.uniform_buffers.len(),
+                        &i.vertex_layout,
+                        i.shader_settings.resolve_depth_compare(i.camera_effect),
                     )?;
-                    self.affected_objects.push(i.name);
+                    if !self.affected_objects.contains(&i.name) {
+                        self.affected_objects.push(i.name);
+                    }
                 }
             }
         }
 
+        // after the light uniforms are added, so the casters are drawn with their final layout
+        renderer.update_shadow_maps(&casters, camera, objects, &light_keys)?;
+
         Ok(())
     }
 
+    /// Makes the object a white point light without shadows
     pub fn set_object_as_light(&mut self, object: &'static str) {
+        self.set_light(object, Light::default())
+    }
+
+    /// Makes the object a light of the given kind, casting shadows if they are set. Every light
+    /// shines on the objects, up to `max_lights`, and up to `shadows::MAX_SHADOW_CASTERS` of
+    /// them cast shadows
+    pub fn set_light(&mut self, object: &'static str, light: Light) {
+        self.lights.insert(object, light);
         self.light_objects.insert(
             object,
             (
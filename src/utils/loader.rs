@@ -1,4 +1,4 @@
-use crate::{Engine, ObjectSettings, Vertex};
+use crate::{Engine, Indices, ObjectSettings, Vertex};
 
 pub fn load_gltf<'a>(
     name: &'static str,
@@ -6,7 +6,7 @@ pub fn load_gltf<'a>(
     engine: &mut Engine,
 ) -> anyhow::Result<()> {
     let mut verticies = Vec::<Vertex>::new();
-    let mut indicies = Vec::<u16>::new();
+    let mut indicies = Vec::<u32>::new();
 
     let (gltf, buffers, _) = gltf::import(path)?;
     for mesh in gltf.meshes() {
@@ -26,6 +26,8 @@ pub fn load_gltf<'a>(
                 }
             }
 
+            // indices of each primitive start from its own first vertex
+            let index_offset = verticies.len() as u32;
             for i in 0..positions.len() {
                 verticies.push(Vertex {
                     position: positions[i],
@@ -35,23 +37,20 @@ pub fn load_gltf<'a>(
             }
 
             if let Some(index) = reader.read_indices() {
-                match index {
-                    gltf::mesh::util::ReadIndices::U16(iter) => {
-                        for i in iter {
-                            indicies.push(i);
-                        }
-                    }
-                    gltf::mesh::util::ReadIndices::U32(iter) => {
-                        for i in iter {
-                            indicies.push(i as u16);
-                        }
-                    }
-                    _ => (),
+                for i in index.into_u32() {
+                    indicies.push(i + index_offset);
                 }
             }
         }
     }
 
+    // 16 bit indices are used whenever all the vertices can be reached with them
+    let indicies = if verticies.len() <= u16::MAX as usize + 1 {
+        Indices::U16(indicies.iter().map(|i| *i as u16).collect())
+    } else {
+        Indices::U32(indicies)
+    };
+
     engine.new_object(name, verticies, indicies, ObjectSettings::default())?;
 
     Ok(())
@@ -4,7 +4,7 @@
  * The license is same as the one on the root.
 */
 
-use crate::header::{Camera, Engine, Object, Renderer, WindowDescriptor};
+use crate::header::{Camera, Engine, Object, PowerPreference, Renderer, WindowDescriptor};
 
 use winit::{
     event::{DeviceEvent, Event, WindowEvent},
@@ -50,14 +50,19 @@ impl Engine {
         let window = Window::new(&event_loop).unwrap();
 
         // The renderer init on current window
-        let mut renderer =
-            futures::executor::block_on(Renderer::new(&window, settings.power_preference))?;
+        let mut renderer = futures::executor::block_on(Renderer::new(
+            Some(&window),
+            window.inner_size(),
+            settings.power_preference,
+            false,
+            settings.sample_count,
+        ))?;
 
         let camera = Camera::new(window.inner_size(), &mut renderer)?;
 
         Ok(Self {
-            window,
-            event_loop,
+            window: Some(window),
+            event_loop: Some(event_loop),
             renderer,
             objects: std::collections::HashMap::new(),
             camera,
@@ -65,6 +70,61 @@ impl Engine {
         })
     }
 
+    /// Creates a new engine without a window. Frames are rendered to an offscreen texture
+    /// of the given size, and are stepped manually through `render_frame`.
+    ///
+    /// Setting `force_fallback_adapter` asks for a software adapter, which allows rendering
+    /// on machines without a GPU.
+    pub fn new_headless(
+        width: u32,
+        height: u32,
+        power_preference: PowerPreference,
+        force_fallback_adapter: bool,
+    ) -> anyhow::Result<Self> {
+        #[cfg(feature = "debug")]
+        let _ = env_logger::try_init();
+
+        let size = winit::dpi::PhysicalSize { width, height };
+
+        let mut renderer = futures::executor::block_on(Renderer::new(
+            None,
+            size,
+            power_preference,
+            force_fallback_adapter,
+            1,
+        ))?;
+
+        let camera = Camera::new(size, &mut renderer)?;
+
+        Ok(Self {
+            window: None,
+            event_loop: None,
+            renderer,
+            objects: std::collections::HashMap::new(),
+            camera,
+            plugins: vec![],
+        })
+    }
+
+    /// Renders a single frame. This is how frames are stepped on a headless engine,
+    /// as there is no update loop to drive them.
+    ///
+    /// Camera and object changes are applied before the frame is drawn.
+    pub fn render_frame(&mut self) -> anyhow::Result<()> {
+        self.camera.update_view_projection(&mut self.renderer)?;
+        for object in self.objects.values_mut() {
+            object.update_changes(&mut self.renderer)?;
+        }
+
+        if let Some((encoder, _view, frame)) =
+            self.renderer.pre_render(&self.objects, &self.camera)?
+        {
+            self.renderer.render(encoder, frame)?;
+        }
+
+        Ok(())
+    }
+
     /// Runs the block of code that you pass to it every frame. The update code is used
     /// to modify the engine on the fly thus creating interactive graphics and making things
     /// happy in the engine!
@@ -88,12 +148,19 @@ impl Engine {
         let Self {
             event_loop,
             mut renderer,
-            mut window,
+            window,
             mut objects,
             mut camera,
             mut plugins,
         } = self;
 
+        let (event_loop, mut window) = match (event_loop, window) {
+            (Some(event_loop), Some(window)) => (event_loop, window),
+            _ => anyhow::bail!(
+                "The update loop needs a window, use render_frame on headless engines"
+            ),
+        };
+
         // and get input events to handle them later
         let mut input = winit_input_helper::WinitInputHelper::new();
         let mut _device_event: winit::event::DeviceEvent =
@@ -133,6 +200,7 @@ impl Engine {
                         "Depth Buffer",
                         &renderer.device,
                         &renderer.config,
+                        renderer.sample_count,
                     );
                     renderer.surface = Some(surface);
                 }
@@ -184,9 +252,8 @@ impl Engine {
                             .update_view_projection(&mut renderer)
                             .expect("Couldn't update camera");
                         objects.iter_mut().for_each(|i| {
-                            if i.1.changed {
-                                i.1.update(&mut renderer).expect("Couldn't update objects");
-                            }
+                            i.1.update_changes(&mut renderer)
+                                .expect("Couldn't update objects");
                         });
 
                         let ren = renderer.render(encoder, frame);
@@ -0,0 +1,1728 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * Golden image tests, run with BLUE_ENGINE_UPDATE_GOLDEN=1 to update the references.
+ * The tests needing features the GL backend lacks are ignored, run them on other adapters
+ * with `cargo test --test golden -- --ignored`
+ *
+ * The license is same as the one on the root.
+*/
+
+use blue_engine::{
+    primitive_shapes::{cube, square, triangle, uv_sphere},
+    utils::golden::GoldenTest,
+    Background, BlendMode, BorderColor, Camera, Engine, FilterMode, HdrSettings, Instance, Light,
+    LightKind, LightManager, MaterialSettings, Mipmaps, ObjectSettings, PostEffect, RenderGraph,
+    RenderGraphPass, SamplerSettings, ShaderSettings, ShadowSettings, TextureData, TextureMode,
+    TextureSettings, TonemapOperator, Vertex, VertexLayout,
+};
+
+fn golden() -> GoldenTest {
+    GoldenTest::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+}
+
+#[test]
+fn default_shader_triangle() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| triangle("Triangle", ObjectSettings::default(), engine),
+            None,
+        )
+        .unwrap();
+
+    golden.check("default_shader_triangle", &frame).unwrap();
+}
+
+#[test]
+fn colored_square_without_camera() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                square(
+                    "Square",
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                    engine,
+                )?;
+                let square = engine.objects.get_mut("Square").unwrap();
+                square.scale(0.5, 0.5, 1.0);
+                square.set_uniform_color(0.0, 0.5, 1.0, 1.0)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("colored_square_without_camera", &frame)
+        .unwrap();
+}
+
+#[test]
+fn cube_from_moved_camera() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                cube("Cube", engine)?;
+                engine.camera.set_position(2.0, 2.0, 4.0)?;
+                engine.camera.set_target(0.0, 0.0, 0.0)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("cube_from_moved_camera", &frame).unwrap();
+}
+
+#[test]
+fn lit_sphere() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.set_light(
+        "Light",
+        Light {
+            intensity: 8.0,
+            ..Default::default()
+        },
+    );
+    light_manager.ambient_strength = 0.1;
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                uv_sphere("Sphere", engine, (18, 36, 1.0))?;
+                cube("Light", engine)?;
+                let light = engine.objects.get_mut("Light").unwrap();
+                light.scale(0.1, 0.1, 0.1);
+                light.position(2.0, 2.0, 2.0);
+                light.set_color(1.0, 1.0, 1.0, 1.0)
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden.check("lit_sphere", &frame).unwrap();
+}
+
+#[test]
+fn render_target_as_texture() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                cube("Cube", engine)?;
+                square(
+                    "Monitor",
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                    engine,
+                )?;
+                let monitor = engine.objects.get_mut("Monitor").unwrap();
+                monitor.scale(0.4, 0.4, 1.0);
+                monitor.translate(1.4, 1.4, 0.0);
+
+                let target = engine.renderer.build_render_target("Monitor", 128, 128)?;
+                let mut camera = Camera::new((128, 128).into(), &mut engine.renderer)?;
+                camera.set_position(3.0, 3.0, 3.0)?;
+                camera.set_target(0.0, 0.0, 0.0)?;
+                engine
+                    .objects
+                    .get_mut("Cube")
+                    .unwrap()
+                    .set_uniform_color(1.0, 0.5, 0.0, 1.0)?;
+                engine
+                    .objects
+                    .get_mut("Cube")
+                    .unwrap()
+                    .update(&mut engine.renderer)?;
+                engine.renderer.render_to_target(
+                    &target,
+                    &engine.objects,
+                    &mut camera,
+                    Some(&["Cube"]),
+                )?;
+
+                let texture = engine.renderer.build_render_target_texture(&target)?;
+                engine
+                    .objects
+                    .get_mut("Monitor")
+                    .unwrap()
+                    .set_texture(texture)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("render_target_as_texture", &frame).unwrap();
+}
+
+#[test]
+fn gradient_background() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                engine.renderer.set_background(Background::Gradient {
+                    top: [0.1, 0.2, 0.8, 1.0],
+                    bottom: [0.9, 0.6, 0.1, 1.0],
+                });
+                triangle("Triangle", ObjectSettings::default(), engine)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("gradient_background", &frame).unwrap();
+}
+
+#[test]
+fn color_background() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                engine
+                    .renderer
+                    .set_background(Background::Color([0.2, 0.6, 0.3, 1.0]));
+                triangle("Triangle", ObjectSettings::default(), engine)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("color_background", &frame).unwrap();
+}
+
+#[test]
+fn background_without_clearing_keeps_previous_frames() {
+    let golden = golden();
+    let mut engine = golden.engine().unwrap();
+    square(
+        "Square",
+        ObjectSettings {
+            camera_effect: false,
+            ..Default::default()
+        },
+        &mut engine,
+    )
+    .unwrap();
+    let square = engine.objects.get_mut("Square").unwrap();
+    square.scale(0.25, 0.25, 1.0);
+    square.translate(-2.0, 0.0, 0.0);
+
+    engine.render_frame().unwrap();
+    engine.renderer.set_background(Background::DontClear);
+    for _ in 0..2 {
+        engine
+            .objects
+            .get_mut("Square")
+            .unwrap()
+            .translate(2.0, 0.0, 0.0);
+        engine.render_frame().unwrap();
+    }
+
+    let frame = engine.renderer.capture_frame().unwrap();
+    golden.check("background_without_clearing", &frame).unwrap();
+}
+
+#[test]
+#[ignore = "needs an adapter that can multisample depth, which the GL backend can't"]
+fn multisampled_triangle() {
+    let golden = golden();
+    let mut engine = golden.engine().unwrap();
+    engine
+        .renderer
+        .set_sample_count(4, &mut engine.objects)
+        .unwrap();
+
+    engine
+        .renderer
+        .set_background(Background::Color([0.1, 0.1, 0.1, 1.0]));
+    triangle("Triangle", ObjectSettings::default(), &mut engine).unwrap();
+    engine.render_frame().unwrap();
+
+    let frame = engine.renderer.capture_frame().unwrap();
+    golden.check("multisampled_triangle", &frame).unwrap();
+}
+
+#[test]
+fn blended_squares_sorted_back_to_front() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                // added front to back, so the draw order has to come from sorting
+                for (name, offset, blend_mode, color) in [
+                    ("Front", 0.4, BlendMode::Alpha, [0.0, 1.0, 0.0, 0.5]),
+                    ("Middle", 0.0, BlendMode::Additive, [0.0, 0.0, 1.0, 0.5]),
+                    ("Back", -0.4, BlendMode::Opaque, [1.0, 0.0, 0.0, 1.0]),
+                ] {
+                    square(name, ObjectSettings::default(), engine)?;
+                    let square = engine.objects.get_mut(name).unwrap();
+                    square.translate(offset, offset, offset);
+                    square.set_blend_mode(blend_mode);
+                    square.set_color(color[0], color[1], color[2], color[3])?;
+                }
+
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("blended_squares_sorted_back_to_front", &frame)
+        .unwrap();
+}
+
+#[test]
+fn layered_squares_stack_in_order() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                // the overlay is behind the others in depth, but on a higher layer
+                for (name, offset, layer, z_index, color) in [
+                    ("Overlay", 0.0, 1, 0, [1.0, 1.0, 1.0, 1.0]),
+                    ("Top", 0.3, 0, 1, [1.0, 0.0, 0.0, 1.0]),
+                    ("Bottom", -0.3, 0, 0, [0.0, 0.0, 1.0, 1.0]),
+                    ("Middle", 0.0, 0, 0, [0.0, 1.0, 0.0, 1.0]),
+                ] {
+                    square(
+                        name,
+                        ObjectSettings {
+                            camera_effect: false,
+                            layer,
+                            z_index,
+                            ..Default::default()
+                        },
+                        engine,
+                    )?;
+                    let square = engine.objects.get_mut(name).unwrap();
+                    square.scale(0.5, 0.5, 1.0);
+                    square.translate(offset, offset, 0.0);
+                    if name == "Overlay" {
+                        square.scale(0.3, 0.3, 1.0);
+                        square.translate(0.0, 0.0, 0.5);
+                    }
+                    square.set_color(color[0], color[1], color[2], color[3])?;
+                }
+
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("layered_squares_stack_in_order", &frame)
+        .unwrap();
+}
+
+#[test]
+fn instanced_squares() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let mut instances = Vec::new();
+                for x in 0..4 {
+                    for y in 0..4 {
+                        instances.push(Instance::new(
+                            (x as f32 * 2.5 - 3.75, y as f32 * 2.5 - 3.75, 0.0),
+                            [x as f32 / 3.0, y as f32 / 3.0, 1.0, 1.0],
+                        ));
+                    }
+                }
+
+                square("Square", ObjectSettings::default(), engine)?;
+                let square = engine.objects.get_mut("Square").unwrap();
+                let (vertices, indices) = (square.vertices.clone(), square.indices.clone());
+                engine.objects.remove("Square");
+
+                engine.new_instanced_object(
+                    "Squares",
+                    vertices,
+                    indices,
+                    instances,
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                )?;
+                engine
+                    .objects
+                    .get_mut("Squares")
+                    .unwrap()
+                    .scale(0.2, 0.2, 1.0);
+
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("instanced_squares", &frame).unwrap();
+}
+
+#[test]
+fn object_changes_between_frames() {
+    let golden = golden();
+    let mut engine = golden.engine().unwrap();
+    square("Square", ObjectSettings::default(), &mut engine).unwrap();
+    triangle("Triangle", ObjectSettings::default(), &mut engine).unwrap();
+    engine.render_frame().unwrap();
+
+    // every change is written into the buffers the first frame allocated
+    let square = engine.objects.get_mut("Square").unwrap();
+    let vertices = square.vertices.clone();
+    square.translate(0.5, 0.5, 0.0);
+    square.scale(0.5, 0.5, 1.0);
+    square.set_color(1.0, 0.5, 0.0, 1.0).unwrap();
+
+    let triangle = engine.objects.get_mut("Triangle").unwrap();
+    triangle.set_vertices(vertices[..3].to_vec(), vec![2u16, 1, 0]);
+    triangle.translate(-0.5, -0.5, 0.0);
+    engine.camera.set_position(0.0, 0.0, 4.0).unwrap();
+    engine.render_frame().unwrap();
+
+    let frame = engine.renderer.capture_frame().unwrap();
+    golden
+        .check("object_changes_between_frames", &frame)
+        .unwrap();
+}
+
+#[test]
+fn square_with_32_bit_indices() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                square("Square", ObjectSettings::default(), engine)?;
+                let square = engine.objects.remove("Square").unwrap();
+
+                // the used vertices come after 70,000 unused ones, out of reach of 16 bit indices
+                let mut vertices = vec![square.vertices[0]; 70_000];
+                vertices.extend(square.vertices.iter());
+                let indices: Vec<u32> = square.indices.iter().map(|i| i + 70_000).collect();
+
+                engine.new_object(
+                    "Large Square",
+                    vertices,
+                    indices,
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                )?;
+                let square = engine.objects.get_mut("Large Square").unwrap();
+                square.scale(0.5, 0.5, 1.0);
+                square.set_color(0.2, 0.8, 0.2, 1.0)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("square_with_32_bit_indices", &frame).unwrap();
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColoredVertex {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+impl VertexLayout for ColoredVertex {
+    fn attributes() -> Vec<(&'static str, wgpu::VertexFormat)> {
+        vec![
+            ("position", wgpu::VertexFormat::Float32x2),
+            ("color", wgpu::VertexFormat::Float32x3),
+        ]
+    }
+}
+
+#[test]
+fn triangle_with_vertex_colors() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                engine.new_object_with_layout(
+                    "Colored Triangle",
+                    vec![
+                        ColoredVertex {
+                            position: [0.0, 0.8],
+                            color: [1.0, 0.0, 0.0],
+                        },
+                        ColoredVertex {
+                            position: [-0.8, -0.8],
+                            color: [0.0, 1.0, 0.0],
+                        },
+                        ColoredVertex {
+                            position: [0.8, -0.8],
+                            color: [0.0, 0.0, 1.0],
+                        },
+                    ],
+                    vec![0u16, 1, 2],
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                )
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("triangle_with_vertex_colors", &frame).unwrap();
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct NormalFirstVertex {
+    normal: [f32; 3],
+    position: [f32; 3],
+}
+
+impl VertexLayout for NormalFirstVertex {
+    fn attributes() -> Vec<(&'static str, wgpu::VertexFormat)> {
+        vec![
+            ("normal", wgpu::VertexFormat::Float32x3),
+            ("position", wgpu::VertexFormat::Float32x3),
+        ]
+    }
+}
+
+#[test]
+fn lit_square_with_its_own_vertex_layout() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.1;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.0, -0.5, -1.0],
+            },
+            ..Default::default()
+        },
+    );
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let normal = [0.0, 0.0, 1.0];
+                engine.new_object_with_layout(
+                    "Square",
+                    [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]]
+                        .iter()
+                        .map(|[x, y]| NormalFirstVertex {
+                            normal,
+                            position: [*x, *y, 0.0],
+                        })
+                        .collect(),
+                    vec![0u16, 1, 2, 2, 3, 0],
+                    ObjectSettings::default(),
+                )?;
+                engine
+                    .objects
+                    .get_mut("Square")
+                    .unwrap()
+                    .set_color(1.0, 0.5, 0.0, 1.0)?;
+                cube("Light", engine)?;
+                engine
+                    .objects
+                    .get_mut("Light")
+                    .unwrap()
+                    .scale(0.1, 0.1, 0.1);
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("lit_square_with_its_own_vertex_layout", &frame)
+        .unwrap();
+
+    // without normals there's nothing to light
+    let mut light_manager = LightManager::new();
+    light_manager.set_light("Light", Light::default());
+    let unlit = golden.render_scene(
+        |engine| {
+            engine.new_object_with_layout(
+                "Colored Triangle",
+                vec![
+                    ColoredVertex {
+                        position: [0.0, 0.8],
+                        color: [1.0, 0.0, 0.0],
+                    };
+                    3
+                ],
+                vec![0u16, 1, 2],
+                ObjectSettings::default(),
+            )?;
+            cube("Light", engine)
+        },
+        Some(&mut light_manager),
+    );
+    assert!(unlit.is_err());
+}
+
+#[test]
+fn render_graph_pass_inverts_the_scene() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                square(
+                    "Square",
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                    engine,
+                )?;
+                let square = engine.objects.get_mut("Square").unwrap();
+                square.scale(0.5, 0.5, 1.0);
+                square.set_color(1.0, 0.5, 0.0, 1.0)?;
+
+                let renderer = &mut engine.renderer;
+                let pipeline = renderer.build_fullscreen_pipeline(
+                    "Invert",
+                    r#"
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(scene_texture, scene_sampler, input.uv);
+    return vec4<f32>(1.0 - color.rgb, 1.0);
+}"#,
+                    &[&renderer.texture_bind_group_layout],
+                    renderer.config.format,
+                    None,
+                    1,
+                );
+
+                let graph = &mut renderer.render_graph;
+                // the scene is drawn into a texture of the graph instead of the frame
+                graph.remove_pass(RenderGraph::SCENE);
+                graph.add_pass(RenderGraphPass::scene("scene color", RenderGraph::DEPTH))?;
+                graph.add_pass(RenderGraphPass {
+                    inputs: vec!["scene color".into()],
+                    color_outputs: vec![RenderGraph::FRAME.into()],
+                    ..RenderGraphPass::new("invert", move |context| {
+                        let scene = context.input_bind_group("scene color")?;
+                        let (view, _) = context.color_attachment(RenderGraph::FRAME)?;
+                        let mut render_pass =
+                            context
+                                .encoder
+                                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                                    label: Some("Invert pass"),
+                                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                        view,
+                                        resolve_target: None,
+                                        ops: wgpu::Operations {
+                                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                            store: true,
+                                        },
+                                    })],
+                                    depth_stencil_attachment: None,
+                                });
+                        render_pass.set_pipeline(&pipeline);
+                        render_pass.set_bind_group(0, scene, &[]);
+                        render_pass.draw(0..3, 0..1);
+                        Ok(())
+                    })
+                })
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("render_graph_pass_inverts_the_scene", &frame)
+        .unwrap();
+}
+
+#[test]
+fn bloom_around_bright_square() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                square(
+                    "Square",
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                    engine,
+                )?;
+                engine
+                    .objects
+                    .get_mut("Square")
+                    .unwrap()
+                    .scale(0.25, 0.25, 1.0);
+                engine.renderer.post_processing = vec![PostEffect::bloom()];
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("bloom_around_bright_square", &frame).unwrap();
+}
+
+#[test]
+fn fxaa_smooths_triangle_edges() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                triangle(
+                    "Triangle",
+                    ObjectSettings {
+                        camera_effect: false,
+                        ..Default::default()
+                    },
+                    engine,
+                )?;
+                engine.renderer.post_processing = vec![PostEffect::fxaa()];
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden.check("fxaa_smooths_triangle_edges", &frame).unwrap();
+}
+
+#[test]
+fn post_effect_parameters_change_between_frames() {
+    let golden = golden();
+    let mut engine = golden.engine().unwrap();
+    square(
+        "Square",
+        ObjectSettings {
+            camera_effect: false,
+            ..Default::default()
+        },
+        &mut engine,
+    )
+    .unwrap();
+    let square = engine.objects.get_mut("Square").unwrap();
+    square.scale(0.5, 0.5, 1.0);
+    square.set_color(0.2, 0.6, 1.0, 1.0).unwrap();
+    engine
+        .renderer
+        .set_background(Background::Color([1.0, 0.9, 0.6, 1.0]));
+    engine.renderer.post_processing = vec![
+        PostEffect::Tonemapping {
+            operator: TonemapOperator::Reinhard,
+            exposure: 1.0,
+        },
+        PostEffect::color_grading(),
+        PostEffect::vignette(),
+    ];
+    engine.render_frame().unwrap();
+
+    engine.renderer.post_processing[1] = PostEffect::ColorGrading {
+        brightness: 0.0,
+        contrast: 1.2,
+        saturation: 0.0,
+        tint: [1.0, 0.9, 0.8],
+    };
+    engine.renderer.post_processing[2] = PostEffect::Vignette {
+        intensity: 1.0,
+        radius: 0.3,
+        smoothness: 0.6,
+    };
+    engine.render_frame().unwrap();
+
+    let frame = engine.renderer.capture_frame().unwrap();
+    golden
+        .check("post_effect_parameters_change_between_frames", &frame)
+        .unwrap();
+
+    // without effects the scene is drawn straight to the frame again
+    engine.renderer.post_processing.clear();
+    engine.render_frame().unwrap();
+    assert_eq!(
+        engine.renderer.render_graph.order().unwrap(),
+        vec![RenderGraph::SCENE]
+    );
+}
+
+#[test]
+#[ignore = "needs an adapter that can render to Rgba16Float, which the GL backend can't"]
+fn hdr_keeps_highlights_above_one() {
+    let golden = golden();
+    let mut engine = golden.engine().unwrap();
+    engine
+        .renderer
+        .set_hdr(Some(HdrSettings::default()), &mut engine.objects)
+        .unwrap();
+
+    for (name, x, brightness) in [
+        ("Dim", -2.0, 0.5),
+        ("Bright", 0.0, 2.0),
+        ("Brighter", 2.0, 8.0),
+    ] {
+        square(
+            name,
+            ObjectSettings {
+                camera_effect: false,
+                ..Default::default()
+            },
+            &mut engine,
+        )
+        .unwrap();
+        let square = engine.objects.get_mut(name).unwrap();
+        square.scale(0.3, 0.3, 1.0);
+        square.translate(x, 0.0, 0.0);
+        square
+            .set_color(brightness, brightness * 0.5, brightness * 0.25, 1.0)
+            .unwrap();
+    }
+    engine.render_frame().unwrap();
+
+    // lowering the exposure brings back the details of the bright squares
+    engine
+        .renderer
+        .set_hdr(
+            Some(HdrSettings {
+                operator: TonemapOperator::Reinhard,
+                exposure: 0.5,
+            }),
+            &mut engine.objects,
+        )
+        .unwrap();
+    engine.render_frame().unwrap();
+
+    let frame = engine.renderer.capture_frame().unwrap();
+    golden
+        .check("hdr_keeps_highlights_above_one", &frame)
+        .unwrap();
+
+    // turning it off again renders straight to the frame
+    engine.renderer.set_hdr(None, &mut engine.objects).unwrap();
+    engine.render_frame().unwrap();
+    assert_eq!(
+        engine.renderer.render_graph.order().unwrap(),
+        vec![RenderGraph::SCENE]
+    );
+}
+
+/// A sphere above a floor lit by the given light, seen from above
+fn shadowed_sphere(engine: &mut Engine) -> anyhow::Result<()> {
+    let normal = [0.0, 1.0, 0.0];
+    engine.new_object(
+        "Floor",
+        vec![
+            Vertex {
+                position: [-3.0, -1.5, -3.0],
+                uv: [0.0, 0.0],
+                normal,
+            },
+            Vertex {
+                position: [-3.0, -1.5, 3.0],
+                uv: [0.0, 1.0],
+                normal,
+            },
+            Vertex {
+                position: [3.0, -1.5, 3.0],
+                uv: [1.0, 1.0],
+                normal,
+            },
+            Vertex {
+                position: [3.0, -1.5, -3.0],
+                uv: [1.0, 0.0],
+                normal,
+            },
+        ],
+        vec![0u16, 1, 2, 2, 3, 0],
+        ObjectSettings {
+            shader_settings: ShaderSettings {
+                cull_mode: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+    uv_sphere("Sphere", engine, (18, 36, 1.0))?;
+    cube("Light", engine)?;
+    let light = engine.objects.get_mut("Light").unwrap();
+    light.scale(0.1, 0.1, 0.1);
+    light.set_color(1.0, 1.0, 1.0, 1.0)?;
+
+    engine.camera.set_position(0.0, 4.0, 5.0)?;
+    engine.camera.set_target(0.0, -1.0, 0.0)
+}
+
+#[test]
+fn directional_light_casts_cascaded_shadows() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.1;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.6, -1.0, -0.3],
+            },
+            shadows: Some(ShadowSettings {
+                distance: 20.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+
+    let frame = golden
+        .render_scene(shadowed_sphere, Some(&mut light_manager))
+        .unwrap();
+
+    golden
+        .check("directional_light_casts_cascaded_shadows", &frame)
+        .unwrap();
+}
+
+#[test]
+fn point_light_casts_shadows_around_it() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.1;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Point,
+            intensity: 12.0,
+            shadows: Some(ShadowSettings {
+                resolution: 512,
+                pcf_radius: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                shadowed_sphere(engine)?;
+                // lights are at the negated position of their object
+                engine
+                    .objects
+                    .get_mut("Light")
+                    .unwrap()
+                    .position(-1.0, -2.5, 0.5);
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("point_light_casts_shadows_around_it", &frame)
+        .unwrap();
+}
+
+fn colored_lights() -> LightManager {
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.05;
+    light_manager.set_light(
+        "Red",
+        Light {
+            color: [1.0, 0.1, 0.1],
+            intensity: 4.0,
+            ..Default::default()
+        },
+    );
+    light_manager.set_light(
+        "Blue",
+        Light {
+            color: [0.1, 0.2, 1.0],
+            intensity: 4.0,
+            range: 3.0,
+            ..Default::default()
+        },
+    );
+    light_manager.set_light(
+        "Sun",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.0, -1.0, 0.0],
+            },
+            intensity: 0.3,
+            ..Default::default()
+        },
+    );
+    light_manager
+}
+
+fn colored_light_cubes(engine: &mut Engine) -> anyhow::Result<()> {
+    shadowed_sphere(engine)?;
+    // the color of the objects doesn't change the color of their light
+    for (name, x) in [("Red", 1.5), ("Blue", -1.5), ("Sun", 0.0)] {
+        cube(name, engine)?;
+        let light = engine.objects.get_mut(name).unwrap();
+        light.scale(0.1, 0.1, 0.1);
+        light.position(x, 0.5, -0.5);
+        light.set_color(0.0, 1.0, 0.0, 1.0)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn colored_lights_add_up() {
+    let golden = golden();
+    let mut light_manager = colored_lights();
+
+    let frame = golden
+        .render_scene(colored_light_cubes, Some(&mut light_manager))
+        .unwrap();
+
+    golden.check("colored_lights_add_up", &frame).unwrap();
+}
+
+#[test]
+fn max_lights_sizes_the_lights_of_the_shaders() {
+    let golden = golden();
+    let mut light_manager = colored_lights();
+    light_manager.max_lights = 3;
+
+    let frame = golden
+        .render_scene(colored_light_cubes, Some(&mut light_manager))
+        .unwrap();
+    // the same lights shine as with room for the default amount
+    golden.check("colored_lights_add_up", &frame).unwrap();
+
+    light_manager.max_lights = 2;
+    assert!(golden
+        .render_scene(colored_light_cubes, Some(&mut light_manager))
+        .is_err());
+}
+
+#[test]
+fn objects_are_lit_by_ambient_light_without_lights() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.5;
+
+    let frame = golden
+        .render_scene(
+            |engine| uv_sphere("Sphere", engine, (18, 36, 1.0)),
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("objects_are_lit_by_ambient_light_without_lights", &frame)
+        .unwrap();
+}
+
+/// A checkerboard floor stretching into the distance, with the texture of the given mip chain
+fn checkerboard_floor(engine: &mut Engine, mipmaps: Mipmaps) -> anyhow::Result<()> {
+    let checkerboard = image::RgbaImage::from_fn(64, 64, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    });
+    let texture = engine.renderer.build_texture_with_settings(
+        "Checkerboard",
+        TextureData::Image(image::DynamicImage::ImageRgba8(checkerboard)),
+        TextureSettings {
+            sampler: SamplerSettings::with_mode(TextureMode::Repeat),
+            mipmaps,
+            ..Default::default()
+        },
+    )?;
+
+    let normal = [0.0, 1.0, 0.0];
+    engine.new_object(
+        "Floor",
+        vec![
+            Vertex {
+                position: [-4.0, -1.0, -30.0],
+                uv: [0.0, 0.0],
+                normal,
+            },
+            Vertex {
+                position: [-4.0, -1.0, 2.0],
+                uv: [0.0, 32.0],
+                normal,
+            },
+            Vertex {
+                position: [4.0, -1.0, 2.0],
+                uv: [8.0, 32.0],
+                normal,
+            },
+            Vertex {
+                position: [4.0, -1.0, -30.0],
+                uv: [8.0, 0.0],
+                normal,
+            },
+        ],
+        vec![0u16, 1, 2, 2, 3, 0],
+        ObjectSettings {
+            shader_settings: ShaderSettings {
+                cull_mode: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+    engine
+        .objects
+        .get_mut("Floor")
+        .unwrap()
+        .set_texture(texture)
+}
+
+#[test]
+fn mipmaps_smooth_a_distant_checkerboard() {
+    // the reference is only smooth with linear filtering within and between the levels
+    let sampler = SamplerSettings::with_mode(TextureMode::Repeat);
+    assert_eq!(sampler.min_filter, FilterMode::Linear);
+    assert_eq!(sampler.mipmap_filter, FilterMode::Linear);
+
+    let golden = golden();
+    let mipmapped = golden
+        .render_scene(|engine| checkerboard_floor(engine, Mipmaps::Full), None)
+        .unwrap();
+    let aliased = golden
+        .render_scene(|engine| checkerboard_floor(engine, Mipmaps::None), None)
+        .unwrap();
+
+    golden
+        .check("mipmaps_smooth_a_distant_checkerboard", &mipmapped)
+        .unwrap();
+    // the far end blends into gray instead of flickering between black and white
+    let comparison = blue_engine::utils::golden::compare_images(&mipmapped, &aliased, 16);
+    assert!(comparison.mismatched_pixels > 100);
+    assert_eq!(Mipmaps::Full.level_count(64, 16), 7);
+    assert_eq!(Mipmaps::Levels(20).level_count(64, 16), 7);
+    assert_eq!(Mipmaps::None.level_count(64, 16), 1);
+}
+
+/// A square on the screen from `left` to `left + 0.6`, showing its texture three times over
+fn textured_quad(
+    engine: &mut Engine,
+    name: &'static str,
+    left: f32,
+    texture: blue_engine::Textures,
+) -> anyhow::Result<()> {
+    let normal = [0.0, 0.0, 1.0];
+    let right = left + 0.6;
+    engine.new_object(
+        name,
+        vec![
+            Vertex {
+                position: [left, 0.3, 0.0],
+                uv: [-1.0, -1.0],
+                normal,
+            },
+            Vertex {
+                position: [left, -0.3, 0.0],
+                uv: [-1.0, 2.0],
+                normal,
+            },
+            Vertex {
+                position: [right, -0.3, 0.0],
+                uv: [2.0, 2.0],
+                normal,
+            },
+            Vertex {
+                position: [right, 0.3, 0.0],
+                uv: [2.0, -1.0],
+                normal,
+            },
+        ],
+        vec![0u16, 1, 2, 2, 3, 0],
+        ObjectSettings {
+            camera_effect: false,
+            ..Default::default()
+        },
+    )?;
+    engine.objects.get_mut(name).unwrap().set_texture(texture)
+}
+
+/// A 2x2 texture with a different color in each corner
+fn corners() -> TextureData {
+    let colors = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 255, 255],
+    ];
+    TextureData::Image(image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(
+        2,
+        2,
+        |x, y| image::Rgba(colors[(y * 2 + x) as usize]),
+    )))
+}
+
+#[test]
+fn texture_modes_match_their_names() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                for (name, left, mode) in [
+                    ("Clamp", -0.95, TextureMode::Clamp),
+                    ("Repeat", -0.3, TextureMode::Repeat),
+                    ("Mirror Repeat", 0.35, TextureMode::MirrorRepeat),
+                ] {
+                    let texture = engine.renderer.build_texture_with_settings(
+                        name,
+                        corners(),
+                        TextureSettings {
+                            sampler: SamplerSettings {
+                                mode_u: mode,
+                                mode_v: mode,
+                                ..SamplerSettings::nearest()
+                            },
+                            mipmaps: Mipmaps::None,
+                            ..Default::default()
+                        },
+                    )?;
+                    textured_quad(engine, name, left, texture)?;
+                }
+
+                let invalid = engine.renderer.build_texture_with_settings(
+                    "Invalid",
+                    corners(),
+                    TextureSettings {
+                        sampler: SamplerSettings::anisotropic(3),
+                        ..Default::default()
+                    },
+                );
+                assert!(invalid.is_err());
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("texture_modes_match_their_names", &frame)
+        .unwrap();
+}
+
+#[test]
+fn border_mode_fills_beyond_the_edges() {
+    let golden = golden();
+    let result = golden.render_scene(
+        |engine| {
+            let texture = engine.renderer.build_texture_with_settings(
+                "Border",
+                corners(),
+                TextureSettings {
+                    sampler: SamplerSettings {
+                        mode_u: TextureMode::Border,
+                        mode_v: TextureMode::Border,
+                        border_color: BorderColor::OpaqueWhite,
+                        ..SamplerSettings::nearest()
+                    },
+                    mipmaps: Mipmaps::None,
+                    ..Default::default()
+                },
+            )?;
+            textured_quad(engine, "Border", -0.3, texture)
+        },
+        None,
+    );
+    let frame = match result {
+        Ok(frame) => frame,
+        Err(error) => {
+            eprintln!("Skipping border_mode_fills_beyond_the_edges: {}", error);
+            return;
+        }
+    };
+
+    golden
+        .check("border_mode_fills_beyond_the_edges", &frame)
+        .unwrap();
+}
+
+#[test]
+fn sprites_show_their_region_of_the_atlas() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let stripes = image::RgbaImage::from_fn(8, 2, |x, _| {
+                    if x % 2 == 0 {
+                        image::Rgba([255, 255, 0, 255])
+                    } else {
+                        image::Rgba([0, 0, 0, 255])
+                    }
+                });
+                let atlas = engine.renderer.build_texture_atlas(
+                    "Atlas",
+                    vec![
+                        ("corners", corners()),
+                        (
+                            "red",
+                            TextureData::Image(image::DynamicImage::ImageRgba8(
+                                image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255])),
+                            )),
+                        ),
+                        (
+                            "stripes",
+                            TextureData::Image(image::DynamicImage::ImageRgba8(stripes)),
+                        ),
+                    ],
+                    TextureSettings {
+                        sampler: SamplerSettings::nearest(),
+                        mipmaps: Mipmaps::None,
+                        ..Default::default()
+                    },
+                )?;
+                assert!(atlas.region("missing").is_none());
+
+                for (name, region, x) in [
+                    ("Corners", "corners", -0.6),
+                    ("Red", "red", 0.0),
+                    ("Stripes", "stripes", 0.6),
+                ] {
+                    square(
+                        name,
+                        ObjectSettings {
+                            camera_effect: false,
+                            ..Default::default()
+                        },
+                        engine,
+                    )?;
+                    let sprite = engine.objects.get_mut(name).unwrap();
+                    sprite.translate(x, 0.0, 0.0);
+                    sprite.scale(0.25, 0.25, 1.0);
+                    sprite.set_sprite(&atlas, region)?;
+                    assert!(sprite.set_sprite(&atlas, "missing").is_err());
+                }
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("sprites_show_their_region_of_the_atlas", &frame)
+        .unwrap();
+}
+
+/// A light manager with a white light from the upper right and a bit of ambient light
+fn key_light() -> LightManager {
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.05;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.5, -0.5, -1.0],
+            },
+            intensity: 3.0,
+            ..Default::default()
+        },
+    );
+    light_manager
+}
+
+#[test]
+fn pbr_spheres_from_rough_dielectric_to_smooth_metal() {
+    let golden = golden();
+    let mut light_manager = key_light();
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                for (name, x, metallic, roughness, emissive) in [
+                    ("Rough", 1.1, 0.0, 1.0, 0.0),
+                    ("Smooth", 0.0, 0.0, 0.3, 0.0),
+                    ("Metal", -1.1, 1.0, 0.3, 0.0),
+                    ("Glowing", 0.0, 0.0, 1.0, 1.0),
+                ] {
+                    uv_sphere(name, engine, (18, 36, 0.5))?;
+                    let material = engine.renderer.build_material(
+                        name,
+                        MaterialSettings {
+                            base_color: [0.9, 0.5, 0.2, 1.0],
+                            metallic,
+                            roughness,
+                            emissive: [0.0, emissive * 0.3, emissive],
+                            ..Default::default()
+                        },
+                    )?;
+                    let sphere = engine.objects.get_mut(name).unwrap();
+                    sphere.position(x, if emissive > 0.0 { 1.1 } else { 0.0 }, 0.0);
+                    sphere.set_material(material);
+                }
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("pbr_spheres_from_rough_dielectric_to_smooth_metal", &frame)
+        .unwrap();
+}
+
+#[test]
+fn updating_a_material_changes_every_object_using_it() {
+    let golden = golden();
+    let mut light_manager = key_light();
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let dielectric = |base_color| MaterialSettings {
+                    base_color,
+                    metallic: 0.0,
+                    roughness: 0.5,
+                    ..Default::default()
+                };
+                let shared = engine
+                    .renderer
+                    .build_material("Shared", dielectric([1.0, 0.0, 0.0, 1.0]))?;
+                let other = engine
+                    .renderer
+                    .build_material("Other", dielectric([0.0, 0.2, 1.0, 1.0]))?;
+                for (name, x, material) in [
+                    ("Left", 1.1, shared),
+                    ("Middle", 0.0, other),
+                    ("Right", -1.1, shared),
+                ] {
+                    uv_sphere(name, engine, (18, 36, 0.5))?;
+                    let sphere = engine.objects.get_mut(name).unwrap();
+                    sphere.position(x, 0.0, 0.0);
+                    sphere.set_material(material);
+                }
+                engine
+                    .renderer
+                    .update_material(shared, dielectric([0.1, 0.8, 0.1, 1.0]))
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("updating_a_material_changes_every_object_using_it", &frame)
+        .unwrap();
+}
+
+#[test]
+fn normal_map_bumps_a_flat_square() {
+    let golden = golden();
+    let mut light_manager = key_light();
+
+    // stripes leaning left and right along the U coordinate
+    let normal_map = image::RgbaImage::from_fn(64, 64, |x, _| {
+        let slope = ((x as f32 / 64.0 * std::f32::consts::TAU * 4.0).sin() * 100.0) as i32;
+        image::Rgba([(128 + slope) as u8, 128, 240, 255])
+    });
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let normal = [0.0, 0.0, 1.0];
+                engine.new_object(
+                    "Square",
+                    vec![
+                        Vertex {
+                            position: [-1.0, 1.0, 0.0],
+                            uv: [0.0, 0.0],
+                            normal,
+                        },
+                        Vertex {
+                            position: [-1.0, -1.0, 0.0],
+                            uv: [0.0, 1.0],
+                            normal,
+                        },
+                        Vertex {
+                            position: [1.0, -1.0, 0.0],
+                            uv: [1.0, 1.0],
+                            normal,
+                        },
+                        Vertex {
+                            position: [1.0, 1.0, 0.0],
+                            uv: [1.0, 0.0],
+                            normal,
+                        },
+                    ],
+                    vec![0u16, 1, 2, 2, 3, 0],
+                    ObjectSettings::default(),
+                )?;
+                let material = engine.renderer.build_material(
+                    "Bumps",
+                    MaterialSettings {
+                        metallic: 0.0,
+                        roughness: 0.5,
+                        normal_texture: Some(TextureData::Image(image::DynamicImage::ImageRgba8(
+                            normal_map.clone(),
+                        ))),
+                        ..Default::default()
+                    },
+                )?;
+                engine
+                    .objects
+                    .get_mut("Square")
+                    .unwrap()
+                    .set_material(material);
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("normal_map_bumps_a_flat_square", &frame)
+        .unwrap();
+}
+
+#[test]
+fn objects_can_opt_out_of_shadows() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.1;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.6, -1.0, -0.3],
+            },
+            shadows: Some(ShadowSettings::default()),
+            ..Default::default()
+        },
+    );
+    let mut unshadowed = LightManager::new();
+    unshadowed.ambient_strength = 0.1;
+    unshadowed.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.6, -1.0, -0.3],
+            },
+            shadows: None,
+            ..Default::default()
+        },
+    );
+
+    let opted_out = golden
+        .render_scene(
+            |engine| {
+                shadowed_sphere(engine)?;
+                engine.objects.get_mut("Sphere").unwrap().cast_shadows = false;
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+    let without_shadows = golden
+        .render_scene(shadowed_sphere, Some(&mut unshadowed))
+        .unwrap();
+
+    // nothing else casts onto the floor, and the sphere faces away from its own shadow
+    let comparison = blue_engine::utils::golden::compare_images(&opted_out, &without_shadows, 2);
+    assert_eq!(comparison.mismatched_pixels, 0);
+}
+
+#[test]
+fn lights_cast_their_own_shadows() {
+    let golden = golden();
+    let mut light_manager = LightManager::new();
+    light_manager.ambient_strength = 0.1;
+    light_manager.set_light(
+        "Light",
+        Light {
+            kind: LightKind::Directional {
+                direction: [0.6, -1.0, -0.3],
+            },
+            intensity: 0.6,
+            shadows: Some(ShadowSettings {
+                resolution: 512,
+                distance: 20.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+    light_manager.set_light(
+        "Second Light",
+        Light {
+            kind: LightKind::Point,
+            intensity: 8.0,
+            shadows: Some(ShadowSettings {
+                resolution: 512,
+                pcf_radius: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+
+    let frame = golden
+        .render_scene(
+            |engine| {
+                shadowed_sphere(engine)?;
+                cube("Second Light", engine)?;
+                // lights are at the negated position of their object
+                let light = engine.objects.get_mut("Second Light").unwrap();
+                light.scale(0.1, 0.1, 0.1);
+                light.position(-1.0, -2.5, 0.5);
+                Ok(())
+            },
+            Some(&mut light_manager),
+        )
+        .unwrap();
+
+    golden
+        .check("lights_cast_their_own_shadows", &frame)
+        .unwrap();
+}
+
+// +X red, -X cyan, +Y green, -Y magenta, +Z blue, -Z yellow, each marked white at the top left
+fn cube_faces() -> [TextureData; 6] {
+    [
+        [255, 0, 0],
+        [0, 255, 255],
+        [0, 255, 0],
+        [255, 0, 255],
+        [0, 0, 255],
+        [255, 255, 0],
+    ]
+    .map(|[r, g, b]| {
+        TextureData::Image(image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(
+            4,
+            4,
+            |x, y| {
+                if x == 0 && y == 0 {
+                    image::Rgba([255, 255, 255, 255])
+                } else {
+                    image::Rgba([r, g, b, 255])
+                }
+            },
+        )))
+    })
+}
+
+fn skybox_scene(engine: &mut Engine, position: [f32; 3]) -> anyhow::Result<()> {
+    let skybox = engine.renderer.build_cubemap(
+        "Skybox",
+        cube_faces(),
+        TextureSettings {
+            sampler: SamplerSettings::nearest(),
+            mipmaps: Mipmaps::None,
+            ..Default::default()
+        },
+    )?;
+    engine.renderer.set_background(Background::Skybox(skybox));
+
+    // looking at the corner between the +X, +Y and -Z faces
+    engine
+        .camera
+        .set_position(position[0], position[1], position[2])?;
+    engine
+        .camera
+        .set_target(position[0] + 1.0, position[1] + 0.6, position[2] - 1.0)
+}
+
+#[test]
+fn skybox_turns_with_the_camera_but_never_moves() {
+    let golden = golden();
+    let frame = golden
+        .render_scene(
+            |engine| {
+                skybox_scene(engine, [0.0, 0.0, 0.0])?;
+                cube("Cube", engine)?;
+                engine
+                    .objects
+                    .get_mut("Cube")
+                    .unwrap()
+                    .translate(1.5, 1.0, -1.5);
+                engine.objects.get_mut("Cube").unwrap().scale(0.3, 0.3, 0.3);
+                Ok(())
+            },
+            None,
+        )
+        .unwrap();
+    golden
+        .check("skybox_turns_with_the_camera_but_never_moves", &frame)
+        .unwrap();
+
+    let here = golden
+        .render_scene(|engine| skybox_scene(engine, [0.0, 0.0, 0.0]), None)
+        .unwrap();
+    let moved = golden
+        .render_scene(|engine| skybox_scene(engine, [30.0, -12.0, 50.0]), None)
+        .unwrap();
+    let comparison = blue_engine::utils::golden::compare_images(&here, &moved, 2);
+    assert_eq!(comparison.mismatched_pixels, 0);
+}
+
+#[test]
+fn equirectangular_panoramas_wrap_around_the_camera() {
+    let golden = golden();
+    // the sky is blue above the horizon and green below it, with a red band
+    // where it faces -Z and a white band where it faces +X
+    let panorama = image::RgbaImage::from_fn(64, 32, |x, y| {
+        if (28..36).contains(&x) {
+            image::Rgba([255, 0, 0, 255])
+        } else if (44..52).contains(&x) {
+            image::Rgba([255, 255, 255, 255])
+        } else if y < 16 {
+            image::Rgba([0, 0, 255, 255])
+        } else {
+            image::Rgba([0, 128, 0, 255])
+        }
+    });
+    let frame = golden
+        .render_scene(
+            |engine| {
+                let skybox = engine.renderer.build_cubemap_from_equirectangular(
+                    "Panorama",
+                    TextureData::Image(image::DynamicImage::ImageRgba8(panorama)),
+                    32,
+                    TextureSettings::default(),
+                )?;
+                assert_eq!(skybox.size, 32);
+                engine.renderer.set_background(Background::Skybox(skybox));
+
+                engine.camera.set_position(0.0, 0.0, 0.0)?;
+                engine.camera.set_target(0.5, 0.0, -1.0)
+            },
+            None,
+        )
+        .unwrap();
+
+    golden
+        .check("equirectangular_panoramas_wrap_around_the_camera", &frame)
+        .unwrap();
+}
@@ -0,0 +1,118 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use blue_engine::{
+    primitive_shapes::square, BlendMode, Engine, LightManager, MaterialSettings, ObjectSettings,
+    PowerPreference,
+};
+
+#[test]
+fn objects_share_cached_pipelines() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+
+    for name in ["First", "Second", "Third"] {
+        square(name, ObjectSettings::default(), &mut engine).unwrap();
+    }
+    engine.render_frame().unwrap();
+    assert_eq!(engine.renderer.pipeline_cache.misses, 1);
+    assert_eq!(engine.renderer.pipeline_cache.hits, 2);
+
+    // moving and recoloring doesn't touch the pipeline
+    let object = engine.objects.get_mut("First").unwrap();
+    object.translate(0.5, 0.0, 0.0);
+    object.set_color(1.0, 0.0, 0.0, 1.0).unwrap();
+    engine.render_frame().unwrap();
+    assert_eq!(engine.renderer.pipeline_cache.misses, 1);
+    assert_eq!(engine.renderer.pipeline_cache.hits, 2);
+
+    // different settings need their own pipeline, which is then shared as well
+    for name in ["First", "Second"] {
+        engine
+            .objects
+            .get_mut(name)
+            .unwrap()
+            .set_blend_mode(BlendMode::Alpha);
+    }
+    engine.render_frame().unwrap();
+    assert_eq!(engine.renderer.pipeline_cache.misses, 2);
+    assert_eq!(engine.renderer.pipeline_cache.hits, 3);
+    assert_eq!(engine.renderer.pipeline_cache.len(), 2);
+    assert!(std::sync::Arc::ptr_eq(
+        &engine.objects["First"].pipeline.shader,
+        &engine.objects["Second"].pipeline.shader
+    ));
+}
+
+#[test]
+fn objects_share_textures_and_material_pipelines() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    let mut light_manager = LightManager::new();
+    light_manager.set_object_as_light("Light");
+
+    for name in ["Light", "Plain", "Red", "Blue", "Also Red"] {
+        square(name, ObjectSettings::default(), &mut engine).unwrap();
+    }
+    assert!(std::sync::Arc::ptr_eq(
+        &engine.objects["Red"].pipeline.texture,
+        &engine.objects["Blue"].pipeline.texture
+    ));
+
+    let red = engine
+        .renderer
+        .build_material(
+            "Red",
+            MaterialSettings {
+                base_color: [1.0, 0.0, 0.0, 1.0],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let blue = engine
+        .renderer
+        .build_material(
+            "Blue",
+            MaterialSettings {
+                base_color: [0.0, 0.0, 1.0, 1.0],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    for (name, material) in [("Red", red), ("Blue", blue), ("Also Red", red)] {
+        engine.objects.get_mut(name).unwrap().set_material(material);
+    }
+    light_manager
+        .update(&mut engine.objects, &mut engine.renderer, &engine.camera)
+        .unwrap();
+    engine.render_frame().unwrap();
+
+    // the materials only differ in their bind groups, so they share a single pipeline
+    let misses = engine.renderer.pipeline_cache.misses;
+    assert!(std::sync::Arc::ptr_eq(
+        &engine.objects["Red"].pipeline.shader,
+        &engine.objects["Blue"].pipeline.shader
+    ));
+    assert!(!std::sync::Arc::ptr_eq(
+        &engine.objects["Red"].pipeline.shader,
+        &engine.objects["Plain"].pipeline.shader
+    ));
+
+    // changing a material doesn't rebuild the shaders of its objects
+    engine
+        .renderer
+        .update_material(
+            red,
+            MaterialSettings {
+                base_color: [0.0, 1.0, 0.0, 1.0],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    light_manager
+        .update(&mut engine.objects, &mut engine.renderer, &engine.camera)
+        .unwrap();
+    engine.render_frame().unwrap();
+    assert_eq!(engine.renderer.pipeline_cache.misses, misses);
+}
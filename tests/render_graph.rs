@@ -0,0 +1,131 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use blue_engine::{
+    primitive_shapes::triangle, Engine, ObjectSettings, PostEffect, PowerPreference, RenderGraph,
+    RenderGraphPass, RenderGraphTexture, RenderGraphTextureSize,
+};
+
+fn pass(
+    name: &'static str,
+    inputs: Vec<&'static str>,
+    outputs: Vec<&'static str>,
+) -> RenderGraphPass {
+    RenderGraphPass {
+        inputs: inputs.into_iter().map(Into::into).collect(),
+        color_outputs: outputs.into_iter().map(Into::into).collect(),
+        ..RenderGraphPass::new(name, |_| Ok(()))
+    }
+}
+
+#[test]
+fn passes_run_after_the_textures_they_read_are_written() {
+    let mut graph = RenderGraph::new();
+    graph.pass_mut(RenderGraph::SCENE).unwrap().color_outputs = vec!["scene color".into()];
+    graph
+        .add_pass(pass("composite", vec!["blurred"], vec![RenderGraph::FRAME]))
+        .unwrap();
+    graph
+        .add_pass(pass("blur", vec!["scene color"], vec!["blurred"]))
+        .unwrap();
+    graph
+        .add_pass(pass("overlay", vec![], vec![RenderGraph::FRAME]))
+        .unwrap();
+
+    assert_eq!(
+        graph.order().unwrap(),
+        vec![RenderGraph::SCENE, "blur", "composite", "overlay"]
+    );
+}
+
+#[test]
+fn invalid_graphs_are_rejected() {
+    let mut graph = RenderGraph::new();
+    assert!(graph
+        .add_pass(pass(RenderGraph::SCENE, vec![], vec![]))
+        .is_err());
+
+    graph
+        .add_pass(pass("missing", vec!["nothing"], vec![]))
+        .unwrap();
+    assert!(graph.order().is_err());
+    graph.remove_pass("missing").unwrap();
+
+    graph.add_pass(pass("first", vec!["b"], vec!["a"])).unwrap();
+    graph
+        .add_pass(pass("second", vec!["a"], vec!["b"]))
+        .unwrap();
+    assert!(graph.order().is_err());
+}
+
+#[test]
+fn passes_only_access_declared_textures() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    triangle("Triangle", ObjectSettings::default(), &mut engine).unwrap();
+
+    let graph = &mut engine.renderer.render_graph;
+    graph
+        .set_texture(
+            "half",
+            RenderGraphTexture {
+                size: RenderGraphTextureSize::Scaled(0.5),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    graph
+        .add_pass(pass("downsample", vec![], vec!["half"]))
+        .unwrap();
+    graph
+        .add_pass(RenderGraphPass {
+            inputs: vec!["half".into()],
+            ..RenderGraphPass::new("check", |context| {
+                assert_eq!(context.size("half")?, (32, 32));
+                context.input_bind_group("half")?;
+                assert!(context.color_attachment("half").is_err());
+                assert!(context.input(RenderGraph::DEPTH).is_err());
+                Ok(())
+            })
+        })
+        .unwrap();
+    engine.render_frame().unwrap();
+
+    // textures are reallocated when their description changes
+    let graph = &mut engine.renderer.render_graph;
+    graph
+        .set_texture(
+            "half",
+            RenderGraphTexture {
+                size: RenderGraphTextureSize::Fixed(16, 8),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    graph.pass_mut("check").unwrap().execute = Box::new(|context| {
+        assert_eq!(context.size("half")?, (16, 8));
+        Ok(())
+    });
+    engine.render_frame().unwrap();
+}
+
+#[test]
+fn any_number_of_post_effects_get_a_pass() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    engine.renderer.post_processing = vec![PostEffect::vignette(); 20];
+    engine.render_frame().unwrap();
+
+    let order = engine.renderer.render_graph.order().unwrap();
+    assert_eq!(order.len(), 21);
+    assert_eq!(order[20], "post effect 19");
+
+    // the passes of removed effects are taken out of the graph
+    engine.renderer.post_processing.truncate(1);
+    engine.render_frame().unwrap();
+    assert_eq!(
+        engine.renderer.render_graph.order().unwrap(),
+        vec![RenderGraph::SCENE, "post effect 0"]
+    );
+}
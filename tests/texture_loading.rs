@@ -0,0 +1,193 @@
+/*
+ * Blue Engine by Elham Aryanpur
+ *
+ * The license is same as the one on the root.
+*/
+
+use blue_engine::{
+    AtlasRegion, Engine, PowerPreference, TextureData, TextureError, TextureMode, TextureSettings,
+};
+
+fn load_error(engine: &mut Engine, data: TextureData) -> TextureError {
+    let error = engine
+        .renderer
+        .build_texture("Broken", data, TextureMode::Clamp)
+        .err()
+        .expect("The texture shouldn't load");
+
+    match error.downcast::<TextureError>() {
+        Ok(error) => error,
+        Err(error) => panic!("Expected a texture error, got {:?}", error),
+    }
+}
+
+fn png() -> Vec<u8> {
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4))
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    png
+}
+
+#[test]
+fn texture_errors_say_what_went_wrong() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+
+    let missing = load_error(
+        &mut engine,
+        TextureData::Path("tests/there_is_no_such_texture.png"),
+    );
+    assert!(
+        matches!(missing, TextureError::NotFound(path) if path.ends_with("there_is_no_such_texture.png"))
+    );
+
+    let garbage = load_error(&mut engine, TextureData::Bytes(b"not an image".to_vec()));
+    assert!(matches!(garbage, TextureError::UnsupportedFormat(_)));
+
+    let mut truncated = png();
+    truncated.truncate(truncated.len() / 2);
+    let truncated = load_error(&mut engine, TextureData::Bytes(truncated));
+    assert!(matches!(truncated, TextureError::Decode(_)));
+
+    let max_dimension = engine.renderer.device.limits().max_texture_dimension_2d;
+    let wide = image::DynamicImage::ImageRgba8(image::RgbaImage::new(max_dimension + 1, 1));
+    let wide = load_error(&mut engine, TextureData::Image(wide));
+    assert!(matches!(
+        wide,
+        TextureError::TooLarge { width, height: 1, .. } if width == max_dimension + 1
+    ));
+
+    assert!(engine
+        .renderer
+        .build_texture("Valid", TextureData::Bytes(png()), TextureMode::Clamp)
+        .is_ok());
+}
+
+#[test]
+fn broken_textures_can_fall_back_to_the_default_texture() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+
+    let texture = engine.renderer.build_texture_with_settings(
+        "Missing",
+        TextureData::Path("tests/there_is_no_such_texture.png"),
+        TextureSettings {
+            fallback: true,
+            ..Default::default()
+        },
+    );
+    assert!(texture.is_ok());
+}
+
+#[cfg(feature = "sprite_sheet_loader")]
+#[test]
+fn sprite_sheets_are_loaded_from_texture_packer_descriptions() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    let sheet = || TextureData::Image(image::DynamicImage::ImageRgba8(image::RgbaImage::new(8, 4)));
+
+    let hash = r#"{
+        "frames": {
+            "idle": { "frame": { "x": 0, "y": 0, "w": 4, "h": 4 }, "rotated": false },
+            "jump": { "frame": { "x": 4, "y": 2, "w": 4, "h": 2 }, "rotated": false }
+        },
+        "meta": { "size": { "w": 8, "h": 4 } }
+    }"#;
+    let atlas = engine
+        .renderer
+        .load_sprite_sheet("Hash", sheet(), hash, TextureSettings::default())
+        .unwrap();
+    assert_eq!(atlas.size, (8, 4));
+    assert_eq!(
+        atlas.region("idle"),
+        Some(AtlasRegion {
+            x: 0.0,
+            y: 0.0,
+            width: 0.5,
+            height: 1.0,
+        })
+    );
+    assert_eq!(
+        atlas.region("jump"),
+        Some(AtlasRegion {
+            x: 0.5,
+            y: 0.5,
+            width: 0.5,
+            height: 0.5,
+        })
+    );
+
+    let array = r#"{
+        "frames": [
+            { "filename": "walk_0", "frame": { "x": 0, "y": 0, "w": 2, "h": 2 } },
+            { "filename": "walk_1", "frame": { "x": 2, "y": 0, "w": 2, "h": 2 } }
+        ]
+    }"#;
+    let atlas = engine
+        .renderer
+        .load_sprite_sheet("Array", sheet(), array, TextureSettings::default())
+        .unwrap();
+    let mut names: Vec<&str> = atlas.region_names().collect();
+    names.sort();
+    assert_eq!(names, ["walk_0", "walk_1"]);
+    assert_eq!(atlas.region("walk_1").unwrap().uv([1.0, 1.0]), [0.5, 0.5]);
+
+    let rotated = r#"{ "frames": { "idle": { "frame": { "x": 0, "y": 0, "w": 4, "h": 4 }, "rotated": true } } }"#;
+    let outside = r#"{ "frames": { "idle": { "frame": { "x": 6, "y": 0, "w": 4, "h": 4 } } } }"#;
+    for description in [rotated, outside, "not json"] {
+        assert!(engine
+            .renderer
+            .load_sprite_sheet("Broken", sheet(), description, TextureSettings::default())
+            .is_err());
+    }
+}
+
+#[test]
+fn atlas_images_need_unique_names() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    let pixel = || TextureData::Image(image::DynamicImage::ImageRgba8(image::RgbaImage::new(1, 1)));
+
+    let atlas = engine.renderer.build_texture_atlas(
+        "Duplicates",
+        vec![("pixel", pixel()), ("pixel", pixel())],
+        TextureSettings::default(),
+    );
+    assert!(atlas.is_err());
+}
+
+#[test]
+fn cubemap_faces_have_to_be_square_and_the_same_size() {
+    let mut engine = Engine::new_headless(64, 64, PowerPreference::LowPower, true).unwrap();
+    let face = |width, height| {
+        TextureData::Image(image::DynamicImage::ImageRgba8(image::RgbaImage::new(
+            width, height,
+        )))
+    };
+
+    let mismatched = [
+        face(4, 4),
+        face(4, 4),
+        face(2, 2),
+        face(4, 4),
+        face(4, 4),
+        face(4, 4),
+    ];
+    assert!(engine
+        .renderer
+        .build_cubemap("Mismatched", mismatched, TextureSettings::default())
+        .is_err());
+
+    let oblong = [(); 6].map(|_| face(4, 2));
+    assert!(engine
+        .renderer
+        .build_cubemap("Oblong", oblong, TextureSettings::default())
+        .is_err());
+
+    let square = [(); 6].map(|_| face(4, 4));
+    let cubemap = engine
+        .renderer
+        .build_cubemap("Square", square, TextureSettings::default())
+        .unwrap();
+    assert_eq!(cubemap.size, 4);
+}